@@ -0,0 +1,123 @@
+use std::time::{Duration, Instant};
+
+use skia_safe::{
+    Codec, Data, Image, ImageInfo,
+    codec::{Options, Result as CodecResult, ZeroInitialized},
+    images,
+};
+
+/// A GIF, APNG or animated WebP with every frame already composited.
+pub struct Animation {
+    pub frames: Vec<(Image, Duration)>,
+    /// How many times the animation plays, `None` loops forever.
+    pub loops: Option<usize>,
+    pub playing: bool,
+    current: usize,
+    played: usize,
+    next: Option<Instant>,
+}
+
+impl Animation {
+    /// Returns `None` for stills, they stay plain `Image`s.
+    /// Takes the encoded `Data` so a still can reuse it without copying the bytes again.
+    pub fn decode(data: Data) -> Option<Self> {
+        let mut codec = Codec::from_data(data)?;
+        let count = codec.get_frame_count();
+        if count < 2 {
+            return None;
+        }
+        let info = ImageInfo::new_n32_premul(codec.dimensions(), None);
+        let row_bytes = info.min_row_bytes();
+        let size = info.compute_byte_size(row_bytes);
+        let mut frames: Vec<(Image, Duration)> = Vec::with_capacity(count);
+        for index in 0..count {
+            let frame = codec.get_frame_info(index)?;
+            // Frames may only hold what changed, so start from the one they build on.
+            // Its pixels are read back from its image, no second buffer is kept per frame.
+            let prior = usize::try_from(frame.required_frame).ok();
+            let mut pixels = match prior {
+                Some(p) => frames.get(p)?.0.peek_pixels()?.bytes()?.to_vec(),
+                None => vec![0; size],
+            };
+            let options = Options {
+                zero_initialized: ZeroInitialized::No,
+                subset: None,
+                frame_index: index,
+                prior_frame: prior,
+            };
+            match codec.get_pixels_with_options(&info, &mut pixels, row_bytes, Some(&options)) {
+                CodecResult::Success | CodecResult::IncompleteInput => (),
+                _ => return None,
+            }
+            let image = images::raster_from_data(&info, Data::new_copy(&pixels), row_bytes)?;
+            // Same as browsers, near-zero delays mean "as fast as you like" and get slowed down.
+            let millis = match frame.duration {
+                d if d <= 10 => 100,
+                d => d as u64,
+            };
+            frames.push((image, Duration::from_millis(millis)));
+        }
+        Some(Self {
+            frames,
+            loops: codec.get_repetition_count().map(|n| n + 1),
+            playing: true,
+            current: 0,
+            played: 0,
+            next: None,
+        })
+    }
+
+    pub fn image(&self) -> &Image {
+        &self.frames[self.current].0
+    }
+
+    pub fn bytes(&self) -> usize {
+        self.frames
+            .iter()
+            .map(|(image, _)| image.image_info().compute_min_byte_size())
+            .sum()
+    }
+
+    pub fn play(&mut self) {
+        if self.loops.is_some_and(|loops| self.played >= loops) {
+            self.rewind();
+        }
+        if !self.playing {
+            self.playing = true;
+            self.next = None;
+        }
+    }
+
+    pub fn pause(&mut self) {
+        self.playing = false;
+    }
+
+    /// Jumps back to the first frame and starts counting loops again.
+    pub fn rewind(&mut self) {
+        self.current = 0;
+        self.played = 0;
+        self.next = None;
+    }
+
+    /// Steps to whatever frame is due at `now`, returns when the next one is due.
+    pub fn tick(&mut self, now: Instant) -> Option<Instant> {
+        if !self.playing {
+            return None;
+        }
+        let mut next = *self.next.get_or_insert(now + self.frames[self.current].1);
+        while next <= now {
+            if self.current + 1 == self.frames.len() {
+                self.played += 1;
+                if self.loops.is_some_and(|loops| self.played >= loops) {
+                    self.playing = false;
+                    self.next = None;
+                    return None;
+                }
+            }
+            self.current = (self.current + 1) % self.frames.len();
+            next += self.frames[self.current].1;
+        }
+        self.next = Some(next);
+        self.next
+    }
+}
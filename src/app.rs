@@ -1,9 +1,11 @@
-use crate::renderer::Cache;
-use juste::element::Message;
+use crate::renderer::{Cache, FontError};
+use juste::{element::Message, style::Font};
 use skia_safe::Canvas;
 use winit::event_loop::ActiveEventLoop;
 
 pub trait App {
     fn draw(&mut self, cache: &mut Cache, canvas: &Canvas);
     fn user_event(&mut self, message: Message, cache: &mut Cache, event_loop: &ActiveEventLoop);
+    /// Called once for every font that didn't load, texts in it are drawn in the default font.
+    fn font_failed(&mut self, _font: &Font, _error: &FontError, _cache: &mut Cache) {}
 }
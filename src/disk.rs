@@ -0,0 +1,259 @@
+use std::{
+    fs::{self, File},
+    path::PathBuf,
+    process,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use reqwest::header::{CACHE_CONTROL, ETAG, HeaderMap, LAST_MODIFIED};
+
+/// On-disk copies of remote images, keyed by URL.
+/// Every entry is a `<key>.bin` body with a `<key>.meta` file holding its URL and validators,
+/// an entry whose URL differs from the one asked for is another URL sharing the key.
+pub struct Disk {
+    pub dir: PathBuf,
+    /// Size cap for all bodies in bytes, the oldest entries go first.
+    pub cap: u64,
+}
+
+/// Validators and freshness of a cached body.
+#[derive(Debug, Default, Clone)]
+pub struct Meta {
+    pub url: String,
+    pub etag: Option<String>,
+    pub modified: Option<String>,
+    /// Unix seconds until which the body is used without asking the server.
+    pub expires: u64,
+}
+
+impl Meta {
+    /// Reads the caching headers of a response, `None` when the body must not be stored.
+    pub fn from_headers(url: &str, headers: &HeaderMap) -> Option<Self> {
+        let get = |name| {
+            headers
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(|v| v.to_string())
+        };
+        let mut max_age = 0;
+        let mut no_cache = false;
+        if let Some(control) = get(CACHE_CONTROL) {
+            for directive in control.split(',').map(|d| d.trim().to_ascii_lowercase()) {
+                match directive.split_once('=') {
+                    Some(("max-age", age)) => max_age = age.trim_matches('"').parse().unwrap_or(0),
+                    _ if directive == "no-store" => return None,
+                    _ if directive == "no-cache" => no_cache = true,
+                    _ => (),
+                }
+            }
+        }
+        // no-cache asks for revalidation whatever max-age says, and wherever it comes.
+        if no_cache {
+            max_age = 0;
+        }
+        Some(Self {
+            url: url.to_string(),
+            etag: get(ETAG),
+            modified: get(LAST_MODIFIED),
+            expires: now().saturating_add(max_age),
+        })
+    }
+
+    pub fn fresh(&self) -> bool {
+        now() < self.expires
+    }
+
+    fn encode(&self) -> String {
+        let mut out = format!("url: {}\nexpires: {}\n", self.url, self.expires);
+        if let Some(etag) = &self.etag {
+            out += &format!("etag: {}\n", etag);
+        }
+        if let Some(modified) = &self.modified {
+            out += &format!("modified: {}\n", modified);
+        }
+        out
+    }
+
+    fn decode(text: &str) -> Self {
+        let mut meta = Self::default();
+        for line in text.lines() {
+            match line.split_once(": ") {
+                Some(("url", v)) => meta.url = v.to_string(),
+                Some(("expires", v)) => meta.expires = v.parse().unwrap_or(0),
+                Some(("etag", v)) => meta.etag = Some(v.to_string()),
+                Some(("modified", v)) => meta.modified = Some(v.to_string()),
+                _ => (),
+            }
+        }
+        meta
+    }
+}
+
+impl Disk {
+    pub fn new(dir: impl Into<PathBuf>, cap: u64) -> Self {
+        let dir = dir.into();
+        let _ = fs::create_dir_all(&dir);
+        Self { dir, cap }
+    }
+
+    pub fn get(&self, url: &str) -> Option<(Meta, Vec<u8>)> {
+        let meta = Meta::decode(&fs::read_to_string(self.path(url, "meta")).ok()?);
+        if meta.url != url {
+            return None;
+        }
+        let body_path = self.path(url, "bin");
+        let body = fs::read(&body_path).ok()?;
+        // Reads count as use, so trimming drops whatever was shown least recently.
+        if let Ok(file) = File::options().write(true).open(&body_path) {
+            let _ = file.set_modified(SystemTime::now());
+        }
+        Some((meta, body))
+    }
+
+    pub fn put(&self, url: &str, meta: &Meta, body: &[u8]) {
+        // Each write gets its own temporary, downloads of the same key may run at once.
+        static WRITES: AtomicU64 = AtomicU64::new(0);
+        let write = WRITES.fetch_add(1, Ordering::Relaxed);
+        let tmp = self.path(url, &format!("{}.{}.tmp", process::id(), write));
+        if fs::write(&tmp, body).is_ok() && fs::rename(&tmp, self.path(url, "bin")).is_ok() {
+            self.touch(url, meta);
+            self.trim();
+        }
+    }
+
+    /// Replaces the validators of an entry without touching its body.
+    pub fn touch(&self, url: &str, meta: &Meta) {
+        let _ = fs::write(self.path(url, "meta"), meta.encode());
+    }
+
+    pub fn remove(&self, url: &str) {
+        let _ = fs::remove_file(self.path(url, "bin"));
+        let _ = fs::remove_file(self.path(url, "meta"));
+    }
+
+    fn trim(&self) {
+        let Ok(dir) = fs::read_dir(&self.dir) else {
+            return;
+        };
+        let mut bodies: Vec<_> = dir
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "bin"))
+            .filter_map(|entry| {
+                let meta = entry.metadata().ok()?;
+                Some((meta.modified().ok()?, meta.len(), entry.path()))
+            })
+            .collect();
+        let mut total: u64 = bodies.iter().map(|(_, len, _)| len).sum();
+        bodies.sort_by_key(|(modified, _, _)| *modified);
+        for (_, len, path) in bodies {
+            if total <= self.cap {
+                break;
+            }
+            let _ = fs::remove_file(&path);
+            let _ = fs::remove_file(path.with_extension("meta"));
+            total -= len;
+        }
+    }
+
+    fn path(&self, url: &str, ext: &str) -> PathBuf {
+        self.dir.join(format!("{:016x}.{}", key(url), ext))
+    }
+}
+
+/// FNV-1a, stable across runs and toolchains unlike `DefaultHasher`.
+fn key(url: &str) -> u64 {
+    url.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn disk(name: &str) -> Disk {
+        let dir = std::env::temp_dir().join(format!("juste_skia_{}_{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        Disk::new(dir, 1024)
+    }
+
+    fn meta(url: &str) -> Meta {
+        Meta {
+            url: url.to_string(),
+            etag: Some("\"v1\"".to_string()),
+            modified: None,
+            expires: now() + 60,
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let disk = disk("round_trip");
+        let url = "http://localhost/a.png";
+        disk.put(url, &meta(url), b"body");
+        let (got, body) = disk.get(url).expect("Entry was not stored");
+        assert_eq!(body, b"body");
+        assert_eq!(got.url, url);
+        assert_eq!(got.etag.as_deref(), Some("\"v1\""));
+        assert!(got.fresh());
+        disk.remove(url);
+        assert!(disk.get(url).is_none());
+        let _ = fs::remove_dir_all(&disk.dir);
+    }
+
+    #[test]
+    fn other_url_under_the_same_key_is_a_miss() {
+        let disk = disk("collision");
+        let url = "http://localhost/a.png";
+        disk.put(url, &meta("http://localhost/b.png"), b"body");
+        assert!(disk.get(url).is_none());
+        let _ = fs::remove_dir_all(&disk.dir);
+    }
+
+    #[test]
+    fn trim_keeps_the_cap() {
+        let disk = disk("trim");
+        for n in 0..4 {
+            let url = format!("http://localhost/{}.png", n);
+            disk.put(&url, &meta(&url), &[0; 400]);
+        }
+        let kept = (0..4)
+            .filter(|n| disk.get(&format!("http://localhost/{}.png", n)).is_some())
+            .count();
+        assert_eq!(kept, 2);
+        let _ = fs::remove_dir_all(&disk.dir);
+    }
+
+    #[test]
+    fn no_store_is_not_kept() {
+        let mut headers = HeaderMap::new();
+        headers.insert(CACHE_CONTROL, "no-store".parse().unwrap());
+        assert!(Meta::from_headers("http://localhost/a.png", &headers).is_none());
+        headers.insert(CACHE_CONTROL, "max-age=60".parse().unwrap());
+        let meta = Meta::from_headers("http://localhost/a.png", &headers).unwrap();
+        assert!(meta.fresh());
+    }
+
+    #[test]
+    fn no_cache_wins_over_max_age() {
+        let mut headers = HeaderMap::new();
+        for control in ["no-cache, max-age=60", "max-age=60, no-cache"] {
+            headers.insert(CACHE_CONTROL, control.parse().unwrap());
+            let meta = Meta::from_headers("http://localhost/a.png", &headers).unwrap();
+            assert!(!meta.fresh(), "{}", control);
+        }
+        headers.insert(
+            CACHE_CONTROL,
+            "max-age=18446744073709551615".parse().unwrap(),
+        );
+        let meta = Meta::from_headers("http://localhost/a.png", &headers).unwrap();
+        assert_eq!(meta.expires, u64::MAX);
+    }
+}
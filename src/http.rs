@@ -0,0 +1,173 @@
+use std::{
+    io::Read,
+    sync::{
+        Arc, Mutex,
+        mpsc::{Sender, channel},
+    },
+    thread,
+    time::Duration,
+};
+
+use reqwest::{
+    Proxy, blocking,
+    header::{HeaderMap, USER_AGENT},
+    redirect,
+};
+
+use crate::renderer::Fail;
+
+/// Settings for the client `Images` downloads `Src::Url` images with.
+pub struct HttpConfig {
+    pub timeout: Option<Duration>,
+    pub connect_timeout: Option<Duration>,
+    pub user_agent: String,
+    /// Routes every request through this proxy, the system proxy is used when `None`.
+    pub proxy: Option<String>,
+    /// How many redirects to follow, 0 turns them off.
+    pub redirects: usize,
+    /// Largest body accepted, in bytes.
+    pub max_body: u64,
+    /// How many downloads may run at once, each gets its own worker thread.
+    pub connections: usize,
+    /// Extra headers sent to every URL starting with the given prefix, e.g. auth for a CDN.
+    pub headers: Vec<(String, HeaderMap)>,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            timeout: Some(Duration::from_secs(30)),
+            connect_timeout: Some(Duration::from_secs(10)),
+            user_agent: concat!("juste_skia/", env!("CARGO_PKG_VERSION")).to_string(),
+            proxy: None,
+            redirects: 10,
+            max_body: 64 * 1024 * 1024,
+            connections: 8,
+            headers: Vec::new(),
+        }
+    }
+}
+
+type Job = Box<dyn FnOnce() + Send>;
+
+pub struct Http {
+    pub client: blocking::Client,
+    pub max_body: u64,
+    pub headers: Vec<(String, HeaderMap)>,
+    jobs: Sender<Job>,
+}
+
+impl Http {
+    pub fn new(config: HttpConfig) -> Result<Self, reqwest::Error> {
+        let mut default_headers = HeaderMap::new();
+        if let Ok(agent) = config.user_agent.parse() {
+            default_headers.insert(USER_AGENT, agent);
+        }
+        let policy = match config.redirects {
+            0 => redirect::Policy::none(),
+            n => redirect::Policy::limited(n),
+        };
+        let mut builder = blocking::Client::builder()
+            .default_headers(default_headers)
+            .redirect(policy)
+            .timeout(config.timeout)
+            .pool_max_idle_per_host(config.connections);
+        if let Some(timeout) = config.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+        if let Some(proxy) = &config.proxy {
+            builder = builder.proxy(Proxy::all(proxy)?);
+        }
+        let (jobs, queue) = channel::<Job>();
+        let queue = Arc::new(Mutex::new(queue));
+        for _ in 0..config.connections.max(1) {
+            let queue = queue.clone();
+            // Workers leave once the `Http` is dropped and the queue runs dry.
+            thread::spawn(move || {
+                loop {
+                    let job = queue.lock().unwrap().recv();
+                    match job {
+                        Ok(job) => job(),
+                        Err(_) => break,
+                    }
+                }
+            });
+        }
+        Ok(Self {
+            client: builder.build()?,
+            max_body: config.max_body,
+            headers: config.headers,
+            jobs,
+        })
+    }
+
+    /// A GET for `url` carrying the headers configured for its prefix.
+    pub fn request(&self, url: &str) -> blocking::RequestBuilder {
+        let mut request = self.client.get(url);
+        for (prefix, headers) in &self.headers {
+            if url.starts_with(prefix.as_str()) {
+                request = request.headers(headers.clone());
+            }
+        }
+        request
+    }
+
+    /// Reads the body of `response`, failing once it grows past `max_body`.
+    pub fn read_body(&self, response: blocking::Response) -> Result<Vec<u8>, Fail> {
+        if response
+            .content_length()
+            .is_some_and(|len| len > self.max_body)
+        {
+            return Err(Fail::TooLarge);
+        }
+        let mut body = Vec::new();
+        response
+            .take(self.max_body + 1)
+            .read_to_end(&mut body)
+            .map_err(|err| Fail::Network(err.to_string()))?;
+        match body.len() as u64 > self.max_body {
+            true => Err(Fail::TooLarge),
+            false => Ok(body),
+        }
+    }
+
+    /// Queues `job` for the next free worker, at most `connections` jobs run at once.
+    pub fn run(&self, job: impl FnOnce() + Send + 'static) {
+        let _ = self.jobs.send(Box::new(job));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    #[test]
+    fn runs_at_most_connections_jobs_at_once() {
+        let http = Http::new(HttpConfig {
+            connections: 2,
+            ..HttpConfig::default()
+        })
+        .unwrap();
+        let running = Arc::new(AtomicUsize::new(0));
+        let most = Arc::new(AtomicUsize::new(0));
+        let (done, finished) = channel();
+        for _ in 0..6 {
+            let (running, most, done) = (running.clone(), most.clone(), done.clone());
+            http.run(move || {
+                let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                most.fetch_max(now, Ordering::SeqCst);
+                thread::sleep(Duration::from_millis(20));
+                running.fetch_sub(1, Ordering::SeqCst);
+                let _ = done.send(());
+            });
+        }
+        for _ in 0..6 {
+            finished
+                .recv_timeout(Duration::from_secs(5))
+                .expect("A job never ran");
+        }
+        assert_eq!(most.load(Ordering::SeqCst), 2);
+    }
+}
@@ -1,6 +1,69 @@
+use std::time::{Duration, Instant};
+
 use juste::io::{Key, Mouse};
+use skia_safe::Point;
 use winit::{event::MouseButton, keyboard::KeyCode};
 
+/// Presses closer than this in time and space count as one double or triple click.
+const MULTI_CLICK: Duration = Duration::from_millis(500);
+const MULTI_CLICK_SLOP: f32 = 4.0;
+
+/// Mouse state selectable texts read, `pressed` and `copy` only last the frame they happened in.
+pub struct Pointer {
+    pub pos: Point,
+    pub down: bool,
+    pub pressed: bool,
+    /// 1 for a single click, 2 for a double, 3 for a triple.
+    pub clicks: u32,
+    /// Control, or command on macOS, is held.
+    pub command: bool,
+    /// The copy shortcut was pressed.
+    pub copy: bool,
+    last: Option<(Instant, Point)>,
+}
+
+impl Default for Pointer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Pointer {
+    pub fn new() -> Self {
+        Self {
+            pos: Point::default(),
+            down: false,
+            pressed: false,
+            clicks: 0,
+            command: false,
+            copy: false,
+            last: None,
+        }
+    }
+
+    pub fn press(&mut self, now: Instant) {
+        let repeat = self.last.is_some_and(|(at, pos)| {
+            now - at < MULTI_CLICK && (pos - self.pos).length() < MULTI_CLICK_SLOP
+        });
+        self.clicks = match repeat {
+            true => self.clicks % 3 + 1,
+            false => 1,
+        };
+        self.last = Some((now, self.pos));
+        self.down = true;
+        self.pressed = true;
+    }
+
+    pub fn release(&mut self) {
+        self.down = false;
+    }
+
+    pub fn end_frame(&mut self) {
+        self.pressed = false;
+        self.copy = false;
+    }
+}
+
 pub fn filter_mouse(button: MouseButton) -> Mouse {
     use winit::event::MouseButton::*;
 
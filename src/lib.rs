@@ -1,5 +1,12 @@
+pub mod anim;
 pub mod app;
+pub mod disk;
+pub mod http;
 pub mod io;
+pub mod look;
 pub mod passes;
 pub mod plug;
 pub mod renderer;
+pub mod svg;
+pub mod text;
+pub mod uri;
@@ -0,0 +1,180 @@
+use juste::{
+    style::{ColorId, DEFAULT, Sheet, Size},
+    util::Vec2,
+};
+use skia_safe::{
+    BlendMode, Color, ColorFilter, CubicResampler, FilterMode, IRect, MipmapMode, Rect,
+    SamplingOptions, TileMode, color_filters,
+};
+
+/// How an image is scaled into the box of its element.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Fit {
+    /// Stretches to the box, ignoring the aspect ratio.
+    #[default]
+    Fill,
+    /// Largest size that still shows the whole image.
+    Contain,
+    /// Smallest size that covers the whole box, the rest is clipped.
+    Cover,
+    /// Native pixel size.
+    None,
+    /// `None` or `Contain`, whichever is smaller.
+    ScaleDown,
+}
+
+impl Fit {
+    /// Where an image of `image` size lands inside `frame`.
+    /// `align` goes from 0.0 (left/top) to 1.0 (right/bottom) for whatever space is left over.
+    pub fn place(self, image: (f32, f32), frame: Rect, align: Vec2<f32>) -> Rect {
+        let (w, h) = image;
+        if w <= 0.0 || h <= 0.0 {
+            return frame;
+        }
+        let (sx, sy) = (frame.width() / w, frame.height() / h);
+        let scale = match self {
+            Fit::Fill => return frame,
+            Fit::Contain => sx.min(sy),
+            Fit::Cover => sx.max(sy),
+            Fit::None => 1.0,
+            Fit::ScaleDown => sx.min(sy).min(1.0),
+        };
+        let (w, h) = (w * scale, h * scale);
+        Rect::from_xywh(
+            frame.left + (frame.width() - w) * align.x,
+            frame.top + (frame.height() - h) * align.y,
+            w,
+            h,
+        )
+    }
+}
+
+/// How pixels are picked when an image is drawn at another size than its own.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Sampling {
+    /// Hard pixel edges, for pixel art.
+    Nearest,
+    #[default]
+    Linear,
+    /// Linear between mip levels, for images drawn much smaller than they are.
+    /// The levels are built on the GPU when the image is uploaded.
+    Mipmap,
+    /// Mitchell cubic, sharper than linear when scaling up.
+    Cubic,
+}
+
+impl Sampling {
+    pub fn options(self) -> SamplingOptions {
+        match self {
+            Sampling::Nearest => SamplingOptions::new(FilterMode::Nearest, MipmapMode::None),
+            Sampling::Linear => SamplingOptions::new(FilterMode::Linear, MipmapMode::None),
+            Sampling::Mipmap => SamplingOptions::new(FilterMode::Linear, MipmapMode::Linear),
+            Sampling::Cubic => CubicResampler::mitchell().into(),
+        }
+    }
+
+    /// Nine-slice only takes a filter mode.
+    pub fn filter(self) -> FilterMode {
+        match self {
+            Sampling::Nearest => FilterMode::Nearest,
+            _ => FilterMode::Linear,
+        }
+    }
+
+    pub fn mipmapped(self) -> bool {
+        self == Sampling::Mipmap
+    }
+}
+
+/// Color filter applied to an image as it is drawn.
+#[derive(Clone, Copy)]
+pub enum Tone {
+    /// Blends a `Sheet` color with the image, `BlendMode::SrcIn` recolors a monochrome icon.
+    Tint(ColorId, BlendMode),
+    Grayscale,
+    /// `brightness` is added to every channel (0.0 keeps it), `contrast` scales around mid gray (1.0 keeps it).
+    Adjust {
+        brightness: f32,
+        contrast: f32,
+    },
+    /// Row-major 4x5 matrix over unpremultiplied RGBA in 0.0..1.0, the last column is an offset.
+    Matrix([f32; 20]),
+}
+
+impl Tone {
+    pub fn color_filter(&self, sheet: &Sheet) -> Option<ColorFilter> {
+        match self {
+            Tone::Tint(col, mode) => {
+                let col = match sheet.colors.get(col) {
+                    Some(c) => c,
+                    None => sheet.colors.get(&DEFAULT)?,
+                };
+                color_filters::blend(Color::from_argb(col.a, col.r, col.g, col.b), *mode)
+            }
+            Tone::Grayscale => {
+                // Rec. 709 luma, same weights css `grayscale()` uses.
+                let (r, g, b) = (0.2126, 0.7152, 0.0722);
+                Some(color_filters::matrix_row_major(
+                    &[
+                        r, g, b, 0.0, 0.0, //
+                        r, g, b, 0.0, 0.0, //
+                        r, g, b, 0.0, 0.0, //
+                        0.0, 0.0, 0.0, 1.0, 0.0,
+                    ],
+                    None,
+                ))
+            }
+            Tone::Adjust {
+                brightness,
+                contrast,
+            } => {
+                let (c, o) = (*contrast, brightness + 0.5 * (1.0 - contrast));
+                Some(color_filters::matrix_row_major(
+                    &[
+                        c, 0.0, 0.0, 0.0, o, //
+                        0.0, c, 0.0, 0.0, o, //
+                        0.0, 0.0, c, 0.0, o, //
+                        0.0, 0.0, 0.0, 1.0, 0.0,
+                    ],
+                    None,
+                ))
+            }
+            Tone::Matrix(matrix) => Some(color_filters::matrix_row_major(matrix, None)),
+        }
+    }
+}
+
+/// Which `Look` a `Genus::Img` takes, the pad id of its style (`Sheet` ids all share one type).
+/// Pads already size the element, so recoloring an image or sharing a palette color with
+/// another leaves its look alone. Give an image its own pad entry to give it its own look.
+pub type LookId = ColorId;
+
+/// Options for how a `Genus::Img` is sized and drawn, shared by a style, see `Images::looks`.
+#[derive(Clone, Copy)]
+pub struct Look {
+    /// Size of the element, `None` or `Size::Child` on an axis takes the image's own pixels.
+    pub size: Option<Vec2<Size>>,
+    pub fit: Fit,
+    pub align: Vec2<f32>,
+    /// Nine-slice center in image pixels, corners keep their size and edges stretch.
+    /// Takes over from `fit` when set.
+    pub nine: Option<IRect>,
+    /// Repeats the image at its native size over the box, takes over from `fit` and `nine`.
+    pub tile: Option<(TileMode, TileMode)>,
+    pub sampling: Sampling,
+    pub tone: Option<Tone>,
+}
+
+impl Default for Look {
+    fn default() -> Self {
+        Self {
+            size: None,
+            fit: Fit::Fill,
+            align: Vec2::new(0.5, 0.5),
+            nine: None,
+            tile: None,
+            sampling: Sampling::Linear,
+            tone: None,
+        }
+    }
+}
@@ -1,12 +1,17 @@
 use juste::{
     element::{Bound, Element, Listeners},
     genus::{Ctx, CursorState, Dirt, Edit, Frame, Genus, Image, Text},
-    style::{ColorId, DEFAULT, Gravity, Pad, Sheet, Size},
-    util::Dir,
+    io::Io,
+    style::{ColorId, DEFAULT, Font, Gravity, Pad, Sheet, Size},
+    util::{Dir, Vec2},
 };
-use skia_safe::{Canvas, ClipOp, Matrix, Paint, Path, Rect};
+use skia_safe::{BlendMode, Canvas, ClipOp, Color, Matrix, Paint, Path, Rect, Shader, TileMode};
 
-use crate::renderer::{Cache, FontAsset};
+use crate::{
+    look::Look,
+    renderer::{Cache, FontAsset, Load},
+    text::{self, ELLIPSIS, Key, Layout, Line, Overflow},
+};
 
 pub fn first_pass(
     element: &mut Element,
@@ -102,10 +107,53 @@ fn calc_box(bound: &mut Bound, cache: &mut Cache, b: &mut Frame, sheet: &mut She
         Size::Func(fun) => fun(&cache.io),
     };
 
+    if let Some(ceil) = &b.ceil {
+        match ceil.x {
+            Size::Window => {
+                if cache.io.window_size.x < bound.dim.x {
+                    bound.dim.x = cache.io.window_size.x;
+                    b.overflow.make_clip();
+                }
+            }
+            Size::Man(m) => {
+                if m < bound.dim.x {
+                    bound.dim.x = m;
+                    b.overflow.make_clip();
+                }
+            }
+            Size::Func(fun) => {
+                let f = fun(&cache.io);
+                if f < bound.dim.x {
+                    bound.dim.x = f;
+                    b.overflow.make_clip();
+                }
+            }
+            _ => (),
+        }
+    }
+    // Before the height, texts wrapping to the box grow taller once narrowed.
+    clamp_texts(bound, cache, b, sheet);
+
     bound.dim.y = match height {
         Size::Window => cache.io.window_size.y,
         Size::Man(man) => man,
         Size::Func(fun) => fun(&cache.io),
+        // A row is as tall as its lowest child ends up, once texts are dropped onto their baseline.
+        Size::Child if matches!(b.gravity, Gravity::Horizontal) => {
+            let (line, bases) = baselines(cache, b, sheet);
+            let mut bases = bases.into_iter();
+            let mut temp_y: f32 = 0.0;
+            b.children.as_mut().map(|c| {
+                c.iter_mut(|child| {
+                    let base = bases.next().flatten();
+                    child.position(|bound| {
+                        let bottom = bound.shadow[2] + bound.dim.y + bound.shadow[3];
+                        temp_y = temp_y.max(baseline_drop(line, bound, base) + bottom);
+                    });
+                })
+            });
+            temp_y
+        }
         Size::Child => b.children.as_mut().map_or(0.0, |c| {
             let mut temp_y = 0.0;
             c.iter_mut(|child| {
@@ -117,55 +165,79 @@ fn calc_box(bound: &mut Bound, cache: &mut Cache, b: &mut Frame, sheet: &mut She
         }),
     };
 
-    match &b.ceil {
-        Some(ceil) => {
-            match ceil.x {
-                Size::Window => {
-                    if cache.io.window_size.x < bound.dim.x {
-                        bound.dim.x = cache.io.window_size.x;
-                        b.overflow.make_clip();
-                    }
+    if let Some(ceil) = &b.ceil {
+        match ceil.y {
+            Size::Window => {
+                if cache.io.window_size.y < bound.dim.y {
+                    bound.dim.y = cache.io.window_size.y;
+                    b.overflow.make_clip();
                 }
-                Size::Man(m) => {
-                    if m < bound.dim.x {
-                        bound.dim.x = m;
-                        b.overflow.make_clip();
-                    }
+            }
+            Size::Man(m) => {
+                if m < bound.dim.y {
+                    bound.dim.y = m;
+                    b.overflow.make_clip();
                 }
-                Size::Func(fun) => {
-                    let f = fun(&cache.io);
-                    if f < bound.dim.x {
-                        bound.dim.x = f;
-                        b.overflow.make_clip();
-                    }
+            }
+            Size::Func(fun) => {
+                let f = fun(&cache.io);
+                if f < bound.dim.y {
+                    bound.dim.y = f;
+                    b.overflow.make_clip();
                 }
-                _ => (),
             }
-            match ceil.y {
-                Size::Window => {
-                    if cache.io.window_size.y < bound.dim.y {
-                        bound.dim.y = cache.io.window_size.y;
-                        b.overflow.make_clip();
-                    }
+            _ => (),
+        }
+    }
+}
+
+/// Narrows text children that have an overflow mode, or wrap to the box, to the width left in it.
+/// Wrapping ones are laid out again at that width and take the height they end up with.
+fn clamp_texts(bound: &Bound, cache: &mut Cache, b: &mut Frame, sheet: &Sheet) {
+    let horizontal = matches!(b.gravity, Gravity::Horizontal);
+    let mut used = 0.0;
+    b.children.as_mut().map(|c| {
+        c.iter_mut(|child| {
+            let layout = match &child.genus {
+                Genus::Text(text) => {
+                    let f = match sheet.fonts.get(&text.style.font) {
+                        Some(f) => f,
+                        None => sheet.fonts.get(&DEFAULT).unwrap(),
+                    };
+                    let f = cache.font.resolve(f, sheet.fonts.get(&DEFAULT).unwrap());
+                    Some((f, cache.font.layout(&text.style.style.pad)))
                 }
-                Size::Man(m) => {
-                    if m < bound.dim.y {
-                        bound.dim.y = m;
-                        b.overflow.make_clip();
-                    }
+                _ => None,
+            };
+            let clamp =
+                layout.is_some_and(|(_, l)| l.overflow != Overflow::Visible || l.wraps_to_parent());
+            let mut width = 0.0;
+            child.position(|child| {
+                let sides = child.shadow[0] + child.shadow[1];
+                if clamp {
+                    let left = match horizontal {
+                        true => bound.dim.x - used,
+                        false => bound.dim.x,
+                    };
+                    child.dim.x = child.dim.x.min((left - sides).max(0.0));
                 }
-                Size::Func(fun) => {
-                    let f = fun(&cache.io);
-                    if f < bound.dim.y {
-                        bound.dim.y = f;
-                        b.overflow.make_clip();
-                    }
+                width = child.dim.x;
+                used += child.dim.x + sides;
+            });
+            if let (Some((f, layout)), Genus::Text(text)) = (layout, &child.genus)
+                && layout.wraps_to_parent()
+            {
+                let col = text_color(text, sheet);
+                let height = cache
+                    .font
+                    .paragraph(&f, &text.style.style.pad, &text.text, col, width)
+                    .map(|paragraph| paragraph.height());
+                if let Some(height) = height {
+                    child.position(|child| child.dim.y = height);
                 }
-                _ => (),
             }
-        }
-        None => (),
-    }
+        });
+    });
 }
 
 fn calc_input(bound: &mut Bound, input: &Edit, sheet: &mut Sheet) {
@@ -189,13 +261,20 @@ fn calc_image(
         None => sheet.pads.get(&DEFAULT).unwrap(),
     };
     put_pad(bound, pad);
+    let look = look(cache, img);
     match cache.image.load(&img.img_path) {
         Some(image) => {
-            bound.dim.x = image.width() as f32;
-            bound.dim.y = image.height() as f32;
+            let (w, h) = (image.width() as f32, image.height() as f32);
+            let window = cache.io.window_size;
+            bound.dim.x = look
+                .size
+                .map_or(w, |s| image_axis(s.x, w, window.x, &cache.io));
+            bound.dim.y = look
+                .size
+                .map_or(h, |s| image_axis(s.y, h, window.y, &cache.io));
         }
         None => {
-            let mut fb_el = (img.fallback)(&cache.io);
+            let mut fb_el = fallback(img, cache);
             silent_first_pass(&mut fb_el, cache, listener, sheet);
             bound.dim.x = fb_el.bound.dim.x;
             bound.dim.y = fb_el.bound.dim.y;
@@ -203,6 +282,38 @@ fn calc_image(
     }
 }
 
+fn image_axis(size: Size, native: f32, window: f32, io: &Io) -> f32 {
+    match size {
+        Size::Window => window,
+        Size::Man(man) => man,
+        Size::Func(fun) => fun(io),
+        Size::Child => native,
+    }
+}
+
+fn look(cache: &Cache, img: &Image) -> Look {
+    cache
+        .image
+        .looks
+        .get(&img.style.pad)
+        .copied()
+        .unwrap_or_default()
+}
+
+fn fallback(img: &Image, cache: &Cache) -> Element {
+    let fb = cache.image.fallbacks.get(&img.img_path);
+    match cache.image.state(&img.img_path) {
+        Load::Failed(fail) => match fb.and_then(|f| f.failed) {
+            Some(f) => f(&cache.io, fail),
+            None => (img.fallback)(&cache.io),
+        },
+        _ => match fb.and_then(|f| f.pending) {
+            Some(f) => f(&cache.io),
+            None => (img.fallback)(&cache.io),
+        },
+    }
+}
+
 fn calc_text(bound: &mut Bound, cache: &mut Cache, text: &Text, sheet: &mut Sheet) {
     let pad = match sheet.pads.get(&text.style.style.pad) {
         Some(p) => p,
@@ -213,12 +324,95 @@ fn calc_text(bound: &mut Bound, cache: &mut Cache, text: &Text, sheet: &mut Shee
         Some(f) => f,
         None => sheet.fonts.get(&DEFAULT).unwrap(),
     };
+    let f = &cache.font.resolve(f, sheet.fonts.get(&DEFAULT).unwrap());
 
-    cache.font.load_asset(f).map(|asset| {
-        let (_, rect) = asset.font.measure_str(&text.text, None);
-        bound.dim.x = rect.width();
-        bound.dim.y = rect.height();
+    // The same paragraph, laid out for the same width, is painted by `pos_text`.
+    // One wrapping to its parent is only measured here, `clamp_texts` picks its width.
+    let col = text_color(text, sheet);
+    let layout = cache.font.layout(&text.style.style.pad);
+    let width = match layout.wraps_to_parent() {
+        true => f32::NAN,
+        false => text_width(cache, &layout),
+    };
+    cache
+        .font
+        .paragraph(f, &text.style.style.pad, &text.text, col, width)
+        .map(|paragraph| {
+            bound.dim.x = match width.is_finite() {
+                true => width,
+                false => paragraph.max_intrinsic_width().ceil(),
+            };
+            bound.dim.y = paragraph.height();
+        });
+}
+
+/// Baseline of each child of a horizontal frame, `None` for those that aren't texts,
+/// and the lowest of them from the top of the row, which texts are lined up on.
+/// Each child's baseline is looked up once and kept for placing it.
+fn baselines(cache: &mut Cache, b: &mut Frame, sheet: &Sheet) -> (f32, Vec<Option<f32>>) {
+    let mut line: f32 = 0.0;
+    let mut bases = Vec::new();
+    b.children.as_mut().map(|c| {
+        c.iter_mut(|child| {
+            let base = baseline(child, cache, sheet);
+            if let Some(base) = base {
+                child.position(|bound| line = line.max(bound.shadow[2] + base));
+            }
+            bases.push(base);
+        })
     });
+    (line, bases)
+}
+
+/// How far a row child with baseline `base` moves down to sit on `line`.
+fn baseline_drop(line: f32, bound: &Bound, base: Option<f32>) -> f32 {
+    base.map_or(0.0, |base| line - bound.shadow[2] - base)
+}
+
+/// Distance from the top of a text element's box to its first baseline, `None` for other elements.
+/// `Bound` has no room for it, so it is worked out from the cached paragraph.
+pub fn baseline(element: &Element, cache: &mut Cache, sheet: &Sheet) -> Option<f32> {
+    let Genus::Text(text) = &element.genus else {
+        return None;
+    };
+    let f = match sheet.fonts.get(&text.style.font) {
+        Some(f) => f,
+        None => sheet.fonts.get(&DEFAULT).unwrap(),
+    };
+    let f = &cache.font.resolve(f, sheet.fonts.get(&DEFAULT).unwrap());
+    let col = text_color(text, sheet);
+    let layout = cache.font.layout(&text.style.style.pad);
+    let width = wrap_width(cache, &layout, &element.bound);
+    cache
+        .font
+        .paragraph(f, &text.style.style.pad, &text.text, col, width)
+        .map(|paragraph| paragraph.alphabetic_baseline())
+}
+
+/// What the lines of a text at `bound` wrap to, the width `clamp_texts` left it
+/// when it wraps to its parent.
+fn wrap_width(cache: &Cache, layout: &Layout, bound: &Bound) -> f32 {
+    match layout.wraps_to_parent() {
+        true => bound.dim.x,
+        false => text_width(cache, layout),
+    }
+}
+
+fn text_width(cache: &Cache, layout: &Layout) -> f32 {
+    match layout.width {
+        Some(Size::Window) => cache.io.window_size.x,
+        Some(Size::Man(man)) => man,
+        Some(Size::Func(fun)) => fun(&cache.io),
+        Some(Size::Child) | None => f32::INFINITY,
+    }
+}
+
+fn text_color(text: &Text, sheet: &Sheet) -> Color {
+    let col = match sheet.colors.get(&text.style.style.color) {
+        Some(c) => c,
+        None => sheet.colors.get(&DEFAULT).unwrap(),
+    };
+    Color::from_argb(col.a, col.r, col.g, col.b)
 }
 
 fn put_pad(bound: &mut Bound, pad: &Pad) {
@@ -280,12 +474,17 @@ fn pos_box(
 
         match b.gravity {
             Gravity::Horizontal => {
+                // Texts share the lowest baseline among them, everything else keeps to the top.
+                let (line, bases) = baselines(cache, b, sheet);
+                let mut bases = bases.into_iter();
                 b.children.as_mut().map(|c| {
                     c.iter_mut(|child| {
+                        let base = bases.next().flatten();
                         child.position(|bound| {
+                            let drop = baseline_drop(line, bound, base);
                             offset_x += bound.shadow[0];
                             bound.pos.x = offset_x;
-                            bound.pos.y = offset_y + bound.shadow[2];
+                            bound.pos.y = offset_y + bound.shadow[2] + drop;
                             offset_x += bound.dim.x + bound.shadow[1];
                         });
                         second_pass(child, canvas, cache, sheet);
@@ -309,47 +508,96 @@ fn pos_box(
     });
 }
 fn pos_img(bound: &mut Bound, canvas: &Canvas, cache: &mut Cache, img: &Image, sheet: &mut Sheet) {
-    match cache.image.load(&img.img_path) {
-        Some(image) => {
-            let rec = Rect::from_xywh(bound.pos.x, bound.pos.y, bound.dim.x, bound.dim.y);
-            let col = match sheet.colors.get(&img.style.color) {
-                Some(c) => c,
-                None => sheet.colors.get(&DEFAULT).unwrap(),
-            };
-            match &bound.angle {
-                Some(angle) => {
-                    scope(canvas, |c| {
-                        let pivot = skia_safe::Point::new(
-                            bound.pos.x + (bound.dim.x / 2.0),
-                            bound.pos.y + (bound.dim.y / 2.0),
-                        );
-                        let matrix = Matrix::rotate_deg_pivot(*angle, pivot);
-                        c.concat(&matrix);
-                        c.draw_image_rect(
-                            image,
-                            None,
-                            rec,
-                            &cache.reusable_paint.set_argb(col.a, col.r, col.g, col.b),
-                        );
-                    });
-                }
-                None => {
-                    canvas.draw_image_rect(
-                        image,
-                        None,
-                        rec,
-                        &cache.reusable_paint.set_argb(col.a, col.r, col.g, col.b),
-                    );
-                }
+    let natural = cache
+        .image
+        .load(&img.img_path)
+        .map(|image| (image.width() as f32, image.height() as f32));
+    let Some(natural) = natural else {
+        let mut fb_el = fallback(img, cache);
+        fb_el.bound = *bound;
+        second_pass(&mut fb_el, canvas, cache, sheet);
+        return;
+    };
+    let look = look(cache, img);
+    if look.sampling.mipmapped() {
+        cache.image.mipmap(&img.img_path);
+    }
+    let rec = Rect::from_xywh(bound.pos.x, bound.pos.y, bound.dim.x, bound.dim.y);
+    let dst = match (look.tile, look.nine) {
+        (None, None) => look.fit.place(natural, rec, look.align),
+        _ => rec,
+    };
+    let col = match sheet.colors.get(&img.style.color) {
+        Some(c) => c,
+        None => sheet.colors.get(&DEFAULT).unwrap(),
+    };
+    // Tiles and nine-slices are drawn from the image at its own size, an svg stays at that size too.
+    let size = match (look.tile, look.nine) {
+        (None, None) => Vec2::new(dst.width(), dst.height()),
+        _ => Vec2::new(natural.0, natural.1),
+    };
+    let Some(image) = cache.image.load_sized(&img.img_path, size) else {
+        return;
+    };
+    // The style color only gives the opacity, tinting is up to `Look::tone`.
+    let paint = cache
+        .reusable_paint
+        .set_argb(col.a, 255, 255, 255)
+        .set_color_filter(look.tone.and_then(|tone| tone.color_filter(sheet)));
+    let clip = dst.left < rec.left
+        || dst.top < rec.top
+        || dst.right > rec.right
+        || dst.bottom > rec.bottom;
+    match (&bound.angle, clip) {
+        (None, false) => draw_image(canvas, image, rec, dst, &look, paint),
+        (angle, _) => scope(canvas, |c| {
+            if let Some(angle) = angle {
+                let pivot = skia_safe::Point::new(
+                    bound.pos.x + (bound.dim.x / 2.0),
+                    bound.pos.y + (bound.dim.y / 2.0),
+                );
+                let matrix = Matrix::rotate_deg_pivot(*angle, pivot);
+                c.concat(&matrix);
+            }
+            if clip {
+                c.clip_rect(rec, ClipOp::Intersect, true);
             }
+            draw_image(c, image, rec, dst, &look, paint);
+        }),
+    }
+    cache.reusable_paint.set_color_filter(None);
+}
+
+fn draw_image(
+    canvas: &Canvas,
+    image: &skia_safe::Image,
+    rec: Rect,
+    dst: Rect,
+    look: &Look,
+    paint: &Paint,
+) {
+    match (look.tile, look.nine) {
+        (Some(modes), _) => {
+            let matrix = Matrix::translate((rec.left, rec.top));
+            let mut paint = paint.clone();
+            paint.set_shader(image.to_shader(modes, look.sampling.options(), &matrix));
+            canvas.draw_rect(rec, &paint);
         }
-        None => {
-            let mut fb_el = (img.fallback)(&cache.io);
-            fb_el.bound = *bound;
-            second_pass(&mut fb_el, canvas, cache, sheet);
+        (None, Some(center)) => {
+            canvas.draw_image_nine(image, center, rec, look.sampling.filter(), Some(paint));
+        }
+        (None, None) => {
+            canvas.draw_image_rect_with_sampling_options(
+                image,
+                None,
+                dst,
+                look.sampling.options(),
+                paint,
+            );
         }
     }
 }
+
 fn pos_input(
     bound: &mut Bound,
     canvas: &Canvas,
@@ -364,485 +612,281 @@ fn pos_input(
         None => sheet.fonts.get(&DEFAULT).unwrap(),
     };
 
-    let font = cache.font.load_asset(f).unwrap();
+    let f = &cache.font.resolve(f, sheet.fonts.get(&DEFAULT).unwrap());
+    let decoration = cache.font.style(&edit.pad).decoration;
+    let Ok(font) = cache.font.load_asset(f) else {
+        return;
+    };
 
-    let mut line_pos = bound.pos + edit.offset;
-    let mut prev_line_idx = Dir::<usize>::Left(0);
-    let mut cursor_pos = line_pos.x;
-    let mut prev_line = line_pos.y;
-    let mut prev_cursor = cursor_pos;
-    let mut prev = &Ctx::Gap;
+    let buffer = &edit.buffer;
+    let get = move |dir| match dir {
+        Dir::Left(l) => &buffer.left[l],
+        Dir::Right(l) => &buffer.right[l],
+    };
+    let origin = bound.pos + edit.offset;
+    let step = edit.char_size.y;
+    let bottom = bound.pos.y + bound.dim.y;
+    // Lines top to bottom down to the end of the box, the right half of the gap buffer
+    // is stored last line first.
+    let lines = move || {
+        buffer
+            .left
+            .iter()
+            .enumerate()
+            .map(|(i, line)| (Dir::Left(i), line))
+            .chain(
+                buffer
+                    .right
+                    .iter()
+                    .enumerate()
+                    .rev()
+                    .map(|(i, line)| (Dir::Right(i), line)),
+            )
+            .enumerate()
+            .map(move |(n, (dir, line))| (dir, line, origin.y + n as f32 * step))
+            .take_while(move |(_, _, y)| *y <= bottom)
+    };
+    // Partly clipped lines at either edge are drawn too.
+    let shown = |y: f32| y + step > bound.pos.y && y < bottom;
     scope(canvas, |c| {
         let rec = Rect::from_xywh(bound.pos.x, bound.pos.y, bound.dim.x, bound.dim.y);
         c.clip_path(&build_path(&rec, bound), ClipOp::Intersect, Some(true));
-        for (i, line) in edit.buffer.left.iter().enumerate() {
-            match bound.inside_y(&line_pos) {
-                false => match line.ctx_buffer.last() {
-                    None => prev = &Ctx::Gap,
-                    Some(ctx) => prev = ctx,
-                },
-                true => {
-                    match line.cursor_state {
-                        CursorState::Display { char_idx } => {
-                            let col = match sheet.colors.get(&edit.cursor.col) {
-                                Some(c) => c,
-                                None => sheet.colors.get(&DEFAULT).unwrap(),
-                            };
-                            let cursor_pos_x = line_pos.x + (char_idx as f32 * edit.char_size.x);
-                            c.draw_rect(
-                                Rect::from_xywh(
-                                    cursor_pos_x,
-                                    line_pos.y,
-                                    edit.cursor.width,
-                                    edit.char_size.y,
-                                ),
-                                &cache.reusable_paint.set_argb(col.a, col.r, col.g, col.b),
-                            );
-                        }
-                        CursorState::Span { start_idx, length } => {
-                            let cursor_pos_x = line_pos.x + (start_idx as f32 * edit.char_size.x);
-                            let col = match sheet.colors.get(&edit.cursor.col) {
-                                Some(c) => c,
-                                None => sheet.colors.get(&DEFAULT).unwrap(),
-                            };
-                            c.draw_rect(
-                                Rect::from_xywh(
-                                    cursor_pos_x,
-                                    line_pos.y,
-                                    edit.cursor.width * length as f32,
-                                    edit.char_size.y,
-                                ),
-                                &cache.reusable_paint.set_argb(col.a, col.r, col.g, col.b),
-                            );
-                        }
-                        CursorState::Hidden => (),
-                    }
-                    line.ctx_buffer.iter().for_each(|ctx| match ctx {
-                        Ctx::Put { idx, col } => {
-                            match prev {
-                                Ctx::Hold { idx } => {
-                                    let buff = match prev_line_idx {
-                                        Dir::Left(l) => &edit.buffer.left[l],
-                                        Dir::Right(l) => &edit.buffer.right[l],
-                                    };
-                                    let buffer = &buff.buffer[idx[0]..idx[1]];
-                                    draw(
-                                        c,
-                                        &font,
-                                        sheet,
-                                        buffer,
-                                        prev_cursor,
-                                        prev_line,
-                                        &DEFAULT,
-                                        &mut cache.reusable_paint,
-                                    );
-                                    prev = &Ctx::Gap;
-                                }
-                                _ => prev = &Ctx::Gap,
-                            }
-                            let buffer = &line.buffer[idx[0]..idx[1]];
-                            draw(
-                                c,
-                                &font,
-                                sheet,
-                                buffer,
-                                cursor_pos,
-                                line_pos.y,
-                                col,
-                                &mut cache.reusable_paint,
-                            );
-                            cursor_pos += buffer.len() as f32;
-                        }
-                        Ctx::Hold { idx } => match prev {
-                            Ctx::Hold { idx } => {
-                                let buff = match prev_line_idx {
-                                    Dir::Left(l) => &edit.buffer.left[l],
-                                    Dir::Right(l) => &edit.buffer.right[l],
-                                };
-                                let buffer = &buff.buffer[idx[0]..idx[1]];
-                                draw(
-                                    canvas,
-                                    &font,
-                                    sheet,
-                                    buffer,
-                                    prev_cursor,
-                                    prev_line,
-                                    &DEFAULT,
-                                    &mut cache.reusable_paint,
-                                );
-                                prev = ctx;
-                                prev_cursor = cursor_pos;
-                                prev_line = line_pos.y;
-                                prev_line_idx = Dir::Left(i);
-                                cursor_pos = edit.char_size.x * (idx[1] - idx[0]) as f32;
-                            }
-                            Ctx::Future {
-                                idx,
-                                col_self: _,
-                                col_next,
-                            } => {
-                                let buffer = &line.buffer[idx[0]..idx[1]];
-                                draw(
-                                    c,
-                                    &font,
-                                    sheet,
-                                    buffer,
-                                    prev_cursor,
-                                    prev_line,
-                                    col_next,
-                                    &mut cache.reusable_paint,
-                                );
-                                prev = &Ctx::Gap;
-                            }
-                            _ => {
-                                prev = ctx;
-                                prev_line = line_pos.y;
-                                prev_cursor = cursor_pos;
-                                prev_line_idx = Dir::Left(i);
-                                cursor_pos = edit.char_size.x * (idx[1] - idx[0]) as f32;
-                            }
-                        },
-                        Ctx::Pull {
-                            idx,
-                            col_self,
-                            col_prev,
-                        } => {
-                            match prev {
-                                Ctx::Hold { idx } => {
-                                    let buff = match prev_line_idx {
-                                        Dir::Left(l) => &edit.buffer.left[l],
-                                        Dir::Right(l) => &edit.buffer.right[l],
-                                    };
-                                    let buffer = &buff.buffer[idx[0]..idx[1]];
-                                    draw(
-                                        c,
-                                        &font,
-                                        sheet,
-                                        buffer,
-                                        prev_cursor,
-                                        prev_line,
-                                        col_prev,
-                                        &mut cache.reusable_paint,
-                                    );
-                                }
-                                _ => (),
-                            }
-                            let buffer = &line.buffer[idx[0]..idx[1]];
-                            draw(
-                                c,
-                                &font,
-                                sheet,
-                                buffer,
-                                cursor_pos,
-                                line_pos.y,
-                                col_self,
-                                &mut cache.reusable_paint,
-                            );
-                            prev = &Ctx::Gap;
-                            cursor_pos += buffer.len() as f32;
-                        }
-                        Ctx::Future {
-                            idx,
-                            col_self,
-                            col_next: _,
-                        } => {
-                            match prev {
-                                Ctx::Hold { idx } => {
-                                    let buff = match prev_line_idx {
-                                        Dir::Left(l) => &edit.buffer.left[l],
-                                        Dir::Right(l) => &edit.buffer.right[l],
-                                    };
-                                    let buffer = &buff.buffer[idx[0]..idx[1]];
-                                    draw(
-                                        c,
-                                        &font,
-                                        sheet,
-                                        buffer,
-                                        prev_cursor,
-                                        prev_line,
-                                        &DEFAULT,
-                                        &mut cache.reusable_paint,
-                                    );
-                                }
-                                Ctx::Future {
-                                    idx,
-                                    col_self: _,
-                                    col_next,
-                                } => {
-                                    let buffer = &line.buffer[idx[0]..idx[1]];
-                                    draw(
-                                        c,
-                                        &font,
-                                        sheet,
-                                        buffer,
-                                        cursor_pos,
-                                        line_pos.y,
-                                        col_next,
-                                        &mut cache.reusable_paint,
-                                    );
-                                    cursor_pos += buffer.len() as f32;
-                                }
-                                _ => {
-                                    let buffer = &line.buffer[idx[0]..idx[1]];
-                                    draw(
-                                        c,
-                                        &font,
-                                        sheet,
-                                        buffer,
-                                        cursor_pos,
-                                        line_pos.y,
-                                        col_self,
-                                        &mut cache.reusable_paint,
-                                    );
-                                    cursor_pos += buffer.len() as f32;
-                                }
-                            }
-                            prev = ctx;
-                        }
-                        Ctx::Gap => cursor_pos += edit.char_size.x,
-                    });
+        let col = match sheet.colors.get(&edit.cursor.col) {
+            Some(c) => c,
+            None => sheet.colors.get(&DEFAULT).unwrap(),
+        };
+        let paint = cache.reusable_paint.set_argb(col.a, col.r, col.g, col.b);
+        for (_, line, y) in lines().filter(|(_, _, y)| shown(*y)) {
+            let (start, end) = match line.cursor_state {
+                CursorState::Display { char_idx } => {
+                    let x = prefix(font, &line.buffer, char_idx);
+                    (x, x + edit.cursor.width)
                 }
+                CursorState::Span { start_idx, length } => (
+                    prefix(font, &line.buffer, start_idx),
+                    prefix(font, &line.buffer, start_idx + length),
+                ),
+                CursorState::Hidden => continue,
+            };
+            c.draw_rect(
+                Rect::from_xywh(origin.x + start, y, end - start, edit.char_size.y),
+                paint,
+            );
+        }
+        let rows = lines().map(|(dir, line, y)| Row {
+            line: dir,
+            ctx: &line.ctx_buffer[..],
+            y,
+            shown: shown(y),
+        });
+        let draws = plan(rows, origin.x, Kind::of, |dir, ctx| match ctx {
+            Ctx::Put { idx, .. }
+            | Ctx::Hold { idx }
+            | Ctx::Pull { idx, .. }
+            | Ctx::Future { idx, .. } => font.token(slice(&get(dir).buffer, idx)).width,
+            Ctx::Gap => font.measure(" "),
+        });
+        for d in draws {
+            let ctx = &get(d.line).ctx_buffer[d.ctx];
+            let token = match ctx {
+                Ctx::Put { idx, .. }
+                | Ctx::Hold { idx }
+                | Ctx::Pull { idx, .. }
+                | Ctx::Future { idx, .. } => slice(&get(d.line).buffer, idx),
+                Ctx::Gap => continue,
+            };
+            let col = match (d.tint, ctx) {
+                (Tint::Own, Ctx::Put { col, .. }) => col,
+                (Tint::Own, Ctx::Pull { col_self, .. } | Ctx::Future { col_self, .. }) => col_self,
+                (Tint::Prev(line, i), _) => match &get(line).ctx_buffer[i] {
+                    Ctx::Pull { col_prev, .. } => col_prev,
+                    _ => &DEFAULT,
+                },
+                (Tint::Next(line, i), _) => match &get(line).ctx_buffer[i] {
+                    Ctx::Future { col_next, .. } => col_next,
+                    _ => &DEFAULT,
+                },
+                _ => &DEFAULT,
+            };
+            draw(
+                c,
+                font,
+                decoration.as_ref(),
+                sheet,
+                token,
+                d.x,
+                d.y,
+                col,
+                &mut cache.reusable_paint,
+            );
+        }
+    });
+}
+
+/// What a highlighter context does to the tokens around it, see `plan`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    Put,
+    /// Waits for the token after it to pick its color.
+    Hold,
+    /// Colors the `Hold` before it.
+    Pull,
+    /// Colors the `Future` or `Hold` after it.
+    Future,
+    Gap,
+}
+
+impl Kind {
+    fn of(ctx: &Ctx) -> Self {
+        match ctx {
+            Ctx::Put { .. } => Kind::Put,
+            Ctx::Hold { .. } => Kind::Hold,
+            Ctx::Pull { .. } => Kind::Pull,
+            Ctx::Future { .. } => Kind::Future,
+            Ctx::Gap => Kind::Gap,
+        }
+    }
+}
+
+/// A line of an input as `plan` reads it.
+struct Row<'a, C> {
+    line: Dir<usize>,
+    ctx: &'a [C],
+    y: f32,
+    /// Reaching into the box, lines outside only settle and pass on what is pending.
+    shown: bool,
+}
+
+/// Whose color a token placed by `plan` takes.
+#[derive(Clone, Copy)]
+enum Tint {
+    Default,
+    /// `col` or `col_self` of its own context.
+    Own,
+    /// `col_prev` of the `Pull` at that line and index.
+    Prev(Dir<usize>, usize),
+    /// `col_next` of the `Future` at that line and index.
+    Next(Dir<usize>, usize),
+}
+
+/// A token placed by `plan`, `ctx` indexes the contexts of `line`.
+#[derive(Clone, Copy)]
+struct Draw {
+    line: Dir<usize>,
+    ctx: usize,
+    x: f32,
+    y: f32,
+    tint: Tint,
+}
+
+/// Places the tokens of `rows` from `left`, in the order they are drawn. A token left pending
+/// carries over to the next line, which may sit in the other half of the gap buffer.
+/// `width` is the advance of a token, or of a space for a `Gap`.
+fn plan<'a, C: 'a>(
+    rows: impl Iterator<Item = Row<'a, C>>,
+    left: f32,
+    kind_of: impl Fn(&C) -> Kind,
+    mut width: impl FnMut(Dir<usize>, &C) -> f32,
+) -> Vec<Draw> {
+    let mut draws = Vec::new();
+    // A `Hold` waiting on the token after it, already placed.
+    let mut held: Option<Draw> = None;
+    // The `Future` right before the current token.
+    let mut future: Option<(Dir<usize>, usize)> = None;
+    for row in rows {
+        if !row.shown {
+            // Only the ends of a line outside the box matter: its first token settles a `Hold`
+            // still waiting, its last one may leave one pending itself.
+            let token = |ctx: &C| kind_of(ctx) != Kind::Gap;
+            let ends = (
+                row.ctx.iter().position(token),
+                row.ctx.iter().rposition(token),
+            );
+            let (Some(first), Some(last)) = ends else {
+                continue;
+            };
+            if let Some(mut waiting) = held.take() {
+                waiting.tint = match kind_of(&row.ctx[first]) {
+                    Kind::Pull => Tint::Prev(row.line, first),
+                    _ => Tint::Default,
+                };
+                draws.push(waiting);
             }
-            line_pos.y += edit.char_size.y;
-            cursor_pos = line_pos.x;
-        }
-
-        for (i, line) in edit.buffer.right.iter().rev().enumerate() {
-            match bound.inside_y(&line_pos) {
-                false => break,
-                true => {
-                    match line.cursor_state {
-                        CursorState::Display { char_idx } => {
-                            let col = match sheet.colors.get(&edit.cursor.col) {
-                                Some(c) => c,
-                                None => sheet.colors.get(&DEFAULT).unwrap(),
-                            };
-                            let cursor_pos_x = line_pos.x + (char_idx as f32 * edit.char_size.x);
-                            c.draw_rect(
-                                Rect::from_xywh(
-                                    cursor_pos_x,
-                                    line_pos.y,
-                                    edit.cursor.width,
-                                    edit.char_size.y,
-                                ),
-                                &cache.reusable_paint.set_argb(col.a, col.r, col.g, col.b),
-                            );
-                        }
-                        CursorState::Span { start_idx, length } => {
-                            let cursor_pos_x = line_pos.x + (start_idx as f32 * edit.char_size.x);
-                            let col = match sheet.colors.get(&edit.cursor.col) {
-                                Some(c) => c,
-                                None => sheet.colors.get(&DEFAULT).unwrap(),
-                            };
-                            c.draw_rect(
-                                Rect::from_xywh(
-                                    cursor_pos_x,
-                                    line_pos.y,
-                                    edit.cursor.width * length as f32,
-                                    edit.char_size.y,
-                                ),
-                                &cache.reusable_paint.set_argb(col.a, col.r, col.g, col.b),
-                            );
-                        }
-                        CursorState::Hidden => (),
-                    }
-                    line.ctx_buffer.iter().for_each(|ctx| match ctx {
-                        Ctx::Put { idx, col } => {
-                            match prev {
-                                Ctx::Hold { idx } => {
-                                    let buff = match prev_line_idx {
-                                        Dir::Left(l) => &edit.buffer.left[l],
-                                        Dir::Right(l) => &edit.buffer.right[l],
-                                    };
-                                    let buffer = &buff.buffer[idx[0]..idx[1]];
-                                    draw(
-                                        c,
-                                        &font,
-                                        sheet,
-                                        buffer,
-                                        prev_cursor,
-                                        prev_line,
-                                        &DEFAULT,
-                                        &mut cache.reusable_paint,
-                                    );
-                                    prev = &Ctx::Gap;
-                                }
-                                _ => prev = &Ctx::Gap,
-                            }
-                            let buffer = &line.buffer[idx[0]..idx[1]];
-                            draw(
-                                c,
-                                &font,
-                                sheet,
-                                buffer,
-                                cursor_pos,
-                                line_pos.y,
-                                col,
-                                &mut cache.reusable_paint,
-                            );
-                            cursor_pos += buffer.len() as f32;
-                        }
-                        Ctx::Hold { idx } => match prev {
-                            Ctx::Hold { idx } => {
-                                let buff = match prev_line_idx {
-                                    Dir::Left(l) => &edit.buffer.left[l],
-                                    Dir::Right(l) => &edit.buffer.right[l],
-                                };
-                                let buffer = &buff.buffer[idx[0]..idx[1]];
-                                draw(
-                                    canvas,
-                                    &font,
-                                    sheet,
-                                    buffer,
-                                    prev_cursor,
-                                    prev_line,
-                                    &DEFAULT,
-                                    &mut cache.reusable_paint,
-                                );
-                                prev = ctx;
-                                prev_cursor = cursor_pos;
-                                prev_line = line_pos.y;
-                                prev_line_idx = Dir::Left(i);
-                                cursor_pos = edit.char_size.x * (idx[1] - idx[0]) as f32;
-                            }
-                            Ctx::Future {
-                                idx,
-                                col_self: _,
-                                col_next,
-                            } => {
-                                let buffer = &line.buffer[idx[0]..idx[1]];
-                                draw(
-                                    c,
-                                    &font,
-                                    sheet,
-                                    buffer,
-                                    prev_cursor,
-                                    prev_line,
-                                    col_next,
-                                    &mut cache.reusable_paint,
-                                );
-                                prev = &Ctx::Gap;
-                            }
-                            _ => {
-                                prev = ctx;
-                                prev_line = line_pos.y;
-                                prev_cursor = cursor_pos;
-                                prev_line_idx = Dir::Left(i);
-                                cursor_pos = edit.char_size.x * (idx[1] - idx[0]) as f32;
-                            }
-                        },
-                        Ctx::Pull {
-                            idx,
-                            col_self,
-                            col_prev,
-                        } => {
-                            match prev {
-                                Ctx::Hold { idx } => {
-                                    let buff = match prev_line_idx {
-                                        Dir::Left(l) => &edit.buffer.left[l],
-                                        Dir::Right(l) => &edit.buffer.right[l],
-                                    };
-                                    let buffer = &buff.buffer[idx[0]..idx[1]];
-                                    draw(
-                                        c,
-                                        &font,
-                                        sheet,
-                                        buffer,
-                                        prev_cursor,
-                                        prev_line,
-                                        col_prev,
-                                        &mut cache.reusable_paint,
-                                    );
-                                }
-                                _ => (),
-                            }
-                            let buffer = &line.buffer[idx[0]..idx[1]];
-                            draw(
-                                c,
-                                &font,
-                                sheet,
-                                buffer,
-                                cursor_pos,
-                                line_pos.y,
-                                col_self,
-                                &mut cache.reusable_paint,
-                            );
-                            prev = &Ctx::Gap;
-                            cursor_pos += buffer.len() as f32;
-                        }
-                        Ctx::Future {
-                            idx,
-                            col_self,
-                            col_next: _,
-                        } => {
-                            match prev {
-                                Ctx::Hold { idx } => {
-                                    let buff = match prev_line_idx {
-                                        Dir::Left(l) => &edit.buffer.left[l],
-                                        Dir::Right(l) => &edit.buffer.right[l],
-                                    };
-                                    let buffer = &buff.buffer[idx[0]..idx[1]];
-                                    draw(
-                                        c,
-                                        &font,
-                                        sheet,
-                                        buffer,
-                                        prev_cursor,
-                                        prev_line,
-                                        &DEFAULT,
-                                        &mut cache.reusable_paint,
-                                    );
-                                }
-                                Ctx::Future {
-                                    idx,
-                                    col_self: _,
-                                    col_next,
-                                } => {
-                                    let buffer = &line.buffer[idx[0]..idx[1]];
-                                    draw(
-                                        c,
-                                        &font,
-                                        sheet,
-                                        buffer,
-                                        cursor_pos,
-                                        line_pos.y,
-                                        col_next,
-                                        &mut cache.reusable_paint,
-                                    );
-                                    cursor_pos += buffer.len() as f32;
-                                }
-                                _ => {
-                                    let buffer = &line.buffer[idx[0]..idx[1]];
-                                    draw(
-                                        c,
-                                        &font,
-                                        sheet,
-                                        buffer,
-                                        cursor_pos,
-                                        line_pos.y,
-                                        col_self,
-                                        &mut cache.reusable_paint,
-                                    );
-                                    cursor_pos += buffer.len() as f32;
-                                }
-                            }
-                            prev = ctx;
-                        }
-                        Ctx::Gap => cursor_pos += edit.char_size.x,
-                    });
+            let before = match row.ctx[..last].iter().rposition(token) {
+                Some(j) => match kind_of(&row.ctx[j]) {
+                    Kind::Future => Some((row.line, j)),
+                    _ => None,
+                },
+                None => future,
+            };
+            (held, future) = match (kind_of(&row.ctx[last]), before) {
+                (Kind::Hold, None) => {
+                    let x = row.ctx[..last]
+                        .iter()
+                        .fold(left, |x, ctx| x + width(row.line, ctx));
+                    let draw = Draw {
+                        line: row.line,
+                        ctx: last,
+                        x,
+                        y: row.y,
+                        tint: Tint::Default,
+                    };
+                    (Some(draw), None)
                 }
+                (Kind::Future, _) => (None, Some((row.line, last))),
+                _ => (None, None),
+            };
+            continue;
+        }
+        let mut x = left;
+        for (i, ctx) in row.ctx.iter().enumerate() {
+            let advance = width(row.line, ctx);
+            let kind = kind_of(ctx);
+            if kind == Kind::Gap {
+                x += advance;
+                continue;
+            }
+            if let Some(mut waiting) = held.take() {
+                waiting.tint = match kind {
+                    Kind::Pull => Tint::Prev(row.line, i),
+                    _ => Tint::Default,
+                };
+                draws.push(waiting);
             }
-            line_pos.y += edit.char_size.y;
-            cursor_pos = line_pos.x;
+            let tint = match (future, kind) {
+                (Some((line, j)), Kind::Future | Kind::Hold) => Some(Tint::Next(line, j)),
+                (_, Kind::Hold) => None,
+                _ => Some(Tint::Own),
+            };
+            let draw = Draw {
+                line: row.line,
+                ctx: i,
+                x,
+                y: row.y,
+                tint: tint.unwrap_or(Tint::Default),
+            };
+            match tint {
+                Some(_) => draws.push(draw),
+                None => held = Some(draw),
+            }
+            future = match kind {
+                Kind::Future => Some((row.line, i)),
+                _ => None,
+            };
+            x += advance;
         }
-    });
+    }
+    // Nothing came after the last held token.
+    draws.extend(held);
+    draws
 }
 
 fn draw(
     canvas: &Canvas,
-    font: &FontAsset,
+    font: &mut FontAsset,
+    decoration: Option<&Line>,
     sheet: &mut Sheet,
-    buffer: &[u8],
+    buffer: &str,
     x: f32,
     y: f32,
     col: &ColorId,
@@ -852,16 +896,48 @@ fn draw(
         Some(c) => c,
         None => sheet.colors.get(&DEFAULT).unwrap(),
     };
-    canvas.draw_str(
-        u8_to_str(buffer),
-        (x, y),
-        &font.font,
-        reusable_paint.set_argb(col.a, col.r, col.g, col.b),
-    );
+    reusable_paint.set_argb(col.a, col.r, col.g, col.b);
+    let token = font.token(buffer);
+    for (blob, offset) in &token.runs {
+        canvas.draw_text_blob(blob, (x + offset, y), reusable_paint);
+    }
+    let width = token.width;
+    if let Some(line) = decoration {
+        text::decorate(canvas, &font.font, line, (x, y), width, reusable_paint);
+    }
 }
 
-fn u8_to_str(u8: &[u8]) -> &str {
-    unsafe { str::from_utf8_unchecked(u8) }
+/// Advance of the first `chars` characters of `line`, where the caret sits after them.
+fn prefix(font: &mut FontAsset, line: &[u8], chars: usize) -> f32 {
+    let text = valid(line);
+    let end = text
+        .char_indices()
+        .nth(chars)
+        .map_or(text.len(), |(i, _)| i);
+    font.measure(&text[..end])
+}
+
+/// The part of `bytes` up to the first invalid UTF-8 sequence.
+fn valid(bytes: &[u8]) -> &str {
+    match str::from_utf8(bytes) {
+        Ok(text) => text,
+        Err(e) => str::from_utf8(&bytes[..e.valid_up_to()]).expect("prefix was just validated"),
+    }
+}
+
+/// Text of a highlighter range, `idx[0]..idx[1]` in bytes.
+/// Ends that split a character or run past the line are moved back to the nearest boundary.
+fn slice<'a>(bytes: &'a [u8], idx: &[usize]) -> &'a str {
+    let text = valid(bytes);
+    let snap = |mut i: usize| {
+        i = i.min(text.len());
+        while !text.is_char_boundary(i) {
+            i -= 1;
+        }
+        i
+    };
+    let end = snap(idx[1]);
+    &text[snap(idx[0]).min(end)..end]
 }
 
 fn highlight(edit: &mut Edit) {
@@ -888,44 +964,120 @@ fn highlight(edit: &mut Edit) {
 }
 
 fn pos_text(bound: &mut Bound, canvas: &Canvas, cache: &mut Cache, text: &Text, sheet: &mut Sheet) {
-    let col = match sheet.colors.get(&text.style.style.color) {
-        Some(c) => c,
-        None => sheet.colors.get(&DEFAULT).unwrap(),
-    };
-
+    let col = text_color(text, sheet);
     let f = match sheet.fonts.get(&text.style.font) {
         Some(f) => f,
         None => sheet.fonts.get(&DEFAULT).unwrap(),
     };
+    let f = &cache.font.resolve(f, sheet.fonts.get(&DEFAULT).unwrap());
 
-    let font = cache.font.load_asset(f).unwrap();
-
-    let (_, met) = font.font.metrics();
-    let pos_y = bound.pos.y + met.ascent.abs();
-    if let Some(angle) = &bound.angle {
-        scope(canvas, |c| {
+    let pad = &text.style.style.pad;
+    let layout = cache.font.layout(pad);
+    let width = wrap_width(cache, &layout, bound);
+    let rich = cache.font.is_rich(pad);
+    // A rich text is never cut with an ellipsis, and would show its spans in its place.
+    let ellipsis = match rich {
+        true => 0.0,
+        false => cache
+            .font
+            .paragraph(f, pad, ELLIPSIS, col, f32::INFINITY)
+            .map_or(0.0, |p| p.max_intrinsic_width()),
+    };
+    let Some(paragraph) = cache.font.paragraph(f, pad, &text.text, col, width) else {
+        return;
+    };
+    let natural = match width.is_finite() {
+        true => width,
+        false => paragraph.max_intrinsic_width().ceil(),
+    };
+    // `clamp_texts` left less room than the text takes.
+    let cut = layout.overflow != Overflow::Visible && bound.dim.x < natural;
+    let shortened = match layout.overflow {
+        // A cut rich text is only clipped, its spans would have to be cut one by one.
+        Overflow::Ellipsis | Overflow::Middle if cut && paragraph.line_number() <= 1 && !rich => {
+            let middle = layout.overflow == Overflow::Middle;
+            Some(text::truncate(
+                paragraph,
+                &text.text,
+                &layout,
+                bound.dim.x,
+                ellipsis,
+                middle,
+            ))
+        }
+        _ => None,
+    };
+    let visible = Rect::from_xywh(bound.pos.x, bound.pos.y, bound.dim.x, bound.dim.y);
+    let key = Key {
+        font: *f,
+        pad: *pad,
+        text: text.text.to_string(),
+        color: col.into(),
+    };
+    let selected = match layout.selectable && shortened.is_none() {
+        true => cache
+            .font
+            .select(&key, visible.tl(), visible, &cache.pointer),
+        false => Vec::new(),
+    };
+    let highlight = cache.font.highlight;
+    let paragraph = match &shortened {
+        Some(short) => cache.font.paragraph(f, pad, short, col, width),
+        None => cache.font.paragraph(f, pad, &text.text, col, width),
+    };
+    let Some(paragraph) = paragraph else {
+        return;
+    };
+    scope(canvas, |c| {
+        if let Some(angle) = &bound.angle {
             let pivot = skia_safe::Point::new(
                 bound.pos.x + (bound.dim.x / 2.0),
                 bound.pos.y + (bound.dim.y / 2.0),
             );
             let matrix = Matrix::rotate_deg_pivot(*angle, pivot);
             c.concat(&matrix);
-            c.draw_str(
-                &*text.text,
-                (bound.pos.x, pos_y),
-                &font.font,
-                &cache.reusable_paint.set_argb(col.a, col.r, col.g, col.b),
-            );
-        });
-    } else {
-        canvas.draw_str(
-            &*text.text,
-            (bound.pos.x, pos_y),
-            &font.font,
-            &cache.reusable_paint.set_argb(col.a, col.r, col.g, col.b),
-        );
+        }
+        for rec in &selected {
+            c.draw_rect(rec, cache.reusable_paint.set_color(highlight));
+        }
+        if !cut {
+            paragraph.paint(c, (bound.pos.x, bound.pos.y));
+            return;
+        }
+        c.clip_rect(visible, ClipOp::Intersect, true);
+        match layout.overflow {
+            Overflow::Fade => {
+                c.save_layer_alpha(visible, 255);
+                paragraph.paint(c, (bound.pos.x, bound.pos.y));
+                fade(c, &visible);
+                c.restore();
+            }
+            _ => paragraph.paint(c, (bound.pos.x, bound.pos.y)),
+        }
+    });
+    if rich {
+        cache.font.place(key, visible.tl());
     }
 }
+
+/// Masks out the right end of `rec` in a gradient, drawn over a layer holding the text.
+fn fade(canvas: &Canvas, rec: &Rect) {
+    let len = (rec.width() / 3.0).min(32.0);
+    let shader = Shader::linear_gradient(
+        ((rec.right - len, rec.top), (rec.right, rec.top)),
+        [Color::BLACK, Color::TRANSPARENT].as_slice(),
+        None,
+        TileMode::Clamp,
+        None,
+        None,
+    );
+    let mut paint = Paint::default();
+    paint.set_shader(shader).set_blend_mode(BlendMode::DstIn);
+    canvas.draw_rect(
+        Rect::new(rec.right - len, rec.top, rec.right, rec.bottom),
+        &paint,
+    );
+}
 fn build_path(rec: &Rect, bound: &mut Bound) -> Path {
     let cx = rec.center_x();
     let cy = rec.center_y();
@@ -948,3 +1100,281 @@ where
     fun(canvas);
     canvas.restore();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Xorshift, enough to shake out boundaries without a dependency.
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0 as usize
+        }
+
+        fn below(&mut self, n: usize) -> usize {
+            self.next() % n
+        }
+    }
+
+    /// Mixed-width text, sometimes ending in a cut character or a stray byte like a line being typed.
+    fn line(rng: &mut Rng) -> Vec<u8> {
+        const CHARS: [char; 8] = ['a', ' ', 'é', 'ß', '中', '\u{94d}', '😀', '\u{1f1eb}'];
+        let mut text = String::new();
+        for _ in 0..rng.below(12) {
+            text.push(CHARS[rng.below(CHARS.len())]);
+        }
+        let mut bytes = text.into_bytes();
+        match rng.below(4) {
+            0 => bytes.truncate(bytes.len().saturating_sub(1)),
+            1 => bytes.push(0xff),
+            _ => (),
+        }
+        bytes
+    }
+
+    #[test]
+    fn valid_is_the_longest_utf8_prefix() {
+        let mut rng = Rng(0x2545f4914f6cdd1d);
+        for _ in 0..10_000 {
+            let bytes = line(&mut rng);
+            let text = valid(&bytes);
+            assert!(bytes.starts_with(text.as_bytes()));
+            assert_eq!(text.len() == bytes.len(), str::from_utf8(&bytes).is_ok());
+        }
+    }
+
+    #[test]
+    fn slice_lands_on_char_boundaries() {
+        let mut rng = Rng(0x9e3779b97f4a7c15);
+        for _ in 0..10_000 {
+            let bytes = line(&mut rng);
+            let text = valid(&bytes);
+            // Past the end of the line as often as inside it.
+            let idx = [
+                rng.below(bytes.len() * 2 + 2),
+                rng.below(bytes.len() * 2 + 2),
+            ];
+            let out = slice(&bytes, &idx);
+            let start = out.as_ptr() as usize - text.as_ptr() as usize;
+            let end = start + out.len();
+            assert!(end <= text.len());
+            assert!(text.is_char_boundary(start) && text.is_char_boundary(end));
+            if idx[0] <= idx[1] && text.get(idx[0]..idx[1]).is_some() {
+                assert_eq!(out, &text[idx[0]..idx[1]]);
+            }
+        }
+    }
+
+    /// A highlighter context as the fuzz below hands it to `plan`, `idx` like the one of a `Ctx`.
+    struct Token {
+        kind: Kind,
+        idx: [usize; 2],
+    }
+
+    #[test]
+    fn plan_over_random_lines_and_ranges() {
+        let mut rng = Rng(0xd1b54a32d192ed03);
+        const KINDS: [Kind; 5] = [Kind::Put, Kind::Hold, Kind::Pull, Kind::Future, Kind::Gap];
+        for _ in 0..2_000 {
+            // Both halves of a gap buffer, with ranges past the end of the line as often as inside it.
+            let half = |rng: &mut Rng| -> Vec<(Vec<u8>, Vec<Token>)> {
+                (0..rng.below(4))
+                    .map(|_| {
+                        let bytes = line(rng);
+                        let ctx = (0..rng.below(6))
+                            .map(|_| Token {
+                                kind: KINDS[rng.below(KINDS.len())],
+                                idx: [
+                                    rng.below(bytes.len() * 2 + 2),
+                                    rng.below(bytes.len() * 2 + 2),
+                                ],
+                            })
+                            .collect();
+                        (bytes, ctx)
+                    })
+                    .collect()
+            };
+            let (left, right) = (half(&mut rng), half(&mut rng));
+            let get = |dir| match dir {
+                Dir::Left(l) => &left[l],
+                Dir::Right(l) => &right[l],
+            };
+            let order: Vec<Dir<usize>> = (0..left.len())
+                .map(Dir::Left)
+                .chain((0..right.len()).rev().map(Dir::Right))
+                .collect();
+            let shown: Vec<bool> = order.iter().map(|_| rng.below(3) > 0).collect();
+            let rows = |shown: &[bool]| {
+                order
+                    .iter()
+                    .zip(shown)
+                    .enumerate()
+                    .map(|(n, (dir, shown))| Row {
+                        line: *dir,
+                        ctx: &get(*dir).1[..],
+                        y: n as f32 * 20.0,
+                        shown: *shown,
+                    })
+                    .collect::<Vec<_>>()
+            };
+            let width = |dir, token: &Token| match token.kind {
+                Kind::Gap => 1.0,
+                _ => slice(&get(dir).0, &token.idx).chars().count() as f32,
+            };
+            let draws = plan(rows(&shown).into_iter(), 0.0, |token| token.kind, width);
+            let same = |a, b| match (a, b) {
+                (Dir::Left(a), Dir::Left(b)) | (Dir::Right(a), Dir::Right(b)) => a == b,
+                _ => false,
+            };
+            // Skipping lines outside the box must not change how the others are tinted.
+            let everything = vec![true; order.len()];
+            let full = plan(rows(&everything).into_iter(), 0.0, |t| t.kind, width);
+
+            let mut drawn = vec![0; draws.len()];
+            for d in &draws {
+                let (bytes, ctx) = get(d.line);
+                let text = valid(bytes);
+                let token = slice(bytes, &ctx[d.ctx].idx);
+                let start = token.as_ptr() as usize - text.as_ptr() as usize;
+                assert!(text.get(start..start + token.len()) == Some(token));
+                assert_ne!(ctx[d.ctx].kind, Kind::Gap);
+                match d.tint {
+                    Tint::Prev(line, i) => assert_eq!(get(line).1[i].kind, Kind::Pull),
+                    Tint::Next(line, i) => assert_eq!(get(line).1[i].kind, Kind::Future),
+                    _ => (),
+                }
+                let row = order.iter().position(|dir| same(*dir, d.line)).unwrap();
+                assert_eq!(d.y, row as f32 * 20.0);
+                let x: f32 = ctx[..d.ctx].iter().map(|t| width(d.line, t)).sum();
+                assert_eq!(d.x, x);
+                let all = full.iter().find(|e| same(e.line, d.line) && e.ctx == d.ctx);
+                assert_eq!(tint(d.tint), tint(all.unwrap().tint));
+                let first = draws
+                    .iter()
+                    .position(|e| same(e.line, d.line) && e.ctx == d.ctx);
+                drawn[first.unwrap()] += 1;
+            }
+            assert!(drawn.iter().all(|n| *n <= 1), "a token was placed twice");
+            // Every token of a shown line is placed, one outside the box at most passes on its last.
+            for (dir, shown) in order.iter().zip(&shown) {
+                let placed = draws.iter().filter(|d| same(d.line, *dir)).count();
+                let tokens = get(*dir).1.iter().filter(|t| t.kind != Kind::Gap).count();
+                match shown {
+                    true => assert_eq!(placed, tokens),
+                    false => assert!(placed <= 1),
+                }
+            }
+        }
+    }
+
+    /// `plan` over `rows`, one line every 20 pixels, tokens 10 wide and gaps 1.
+    fn placed(rows: &[(Dir<usize>, &[Kind], bool)]) -> Vec<String> {
+        let rows = rows.iter().enumerate().map(|(n, (line, ctx, shown))| Row {
+            line: *line,
+            ctx,
+            y: n as f32 * 20.0,
+            shown: *shown,
+        });
+        let draws = plan(
+            rows,
+            0.0,
+            |kind| *kind,
+            |_, kind| match kind {
+                Kind::Gap => 1.0,
+                _ => 10.0,
+            },
+        );
+        draws
+            .iter()
+            .map(|d| format!("{}.{} {},{} {}", at(d.line), d.ctx, d.x, d.y, tint(d.tint)))
+            .collect()
+    }
+
+    fn at(dir: Dir<usize>) -> String {
+        match dir {
+            Dir::Left(i) => format!("l{}", i),
+            Dir::Right(i) => format!("r{}", i),
+        }
+    }
+
+    fn tint(tint: Tint) -> String {
+        match tint {
+            Tint::Default => "default".to_string(),
+            Tint::Own => "own".to_string(),
+            Tint::Prev(line, i) => format!("prev {}.{}", at(line), i),
+            Tint::Next(line, i) => format!("next {}.{}", at(line), i),
+        }
+    }
+
+    #[test]
+    fn token_split_by_the_gap() {
+        // The caret sits between the two halves of a token, the line after it is the last of `right`.
+        let before = [Kind::Put, Kind::Gap, Kind::Hold];
+        let after = [Kind::Pull, Kind::Put];
+        let rows = [
+            (Dir::Left(0), &before[..], true),
+            (Dir::Right(1), &after[..], true),
+            (Dir::Right(0), &[Kind::Put][..], true),
+        ];
+        assert_eq!(
+            placed(&rows),
+            [
+                "l0.0 0,0 own",
+                "l0.2 11,0 prev r1.0",
+                "r1.0 0,20 own",
+                "r1.1 10,20 own",
+                "r0.0 0,40 own",
+            ]
+        );
+    }
+
+    #[test]
+    fn every_kind_across_the_caret() {
+        let kinds = [Kind::Put, Kind::Hold, Kind::Pull, Kind::Future];
+        for before in kinds {
+            for after in kinds {
+                let (first, second) = ([before], [after]);
+                let rows = [
+                    (Dir::Left(0), &first[..], true),
+                    (Dir::Right(0), &second[..], true),
+                ];
+                let tints: Vec<String> = placed(&rows)
+                    .iter()
+                    .map(|d| d.split_once(' ').unwrap().1.to_string())
+                    .collect();
+                let expected = match (before, after) {
+                    (Kind::Hold, Kind::Pull) => ["0,0 prev r0.0", "0,20 own"],
+                    (Kind::Hold, Kind::Hold) => ["0,0 default", "0,20 default"],
+                    (Kind::Hold, _) => ["0,0 default", "0,20 own"],
+                    (Kind::Future, Kind::Future | Kind::Hold) => ["0,0 own", "0,20 next l0.0"],
+                    (_, Kind::Hold) => ["0,0 own", "0,20 default"],
+                    (_, _) => ["0,0 own", "0,20 own"],
+                };
+                assert_eq!(tints, expected, "{:?} then {:?}", before, after);
+            }
+        }
+    }
+
+    #[test]
+    fn hidden_lines_pass_on_what_is_pending() {
+        let rows = [
+            (Dir::Left(0), &[Kind::Put, Kind::Hold][..], false),
+            (Dir::Left(1), &[Kind::Pull][..], true),
+            (Dir::Right(1), &[Kind::Put, Kind::Future][..], false),
+            (Dir::Right(0), &[Kind::Hold][..], true),
+        ];
+        assert_eq!(
+            placed(&rows),
+            [
+                "l0.1 10,0 prev l1.0",
+                "l1.0 0,20 own",
+                "r0.0 0,60 next r1.1"
+            ]
+        );
+    }
+}
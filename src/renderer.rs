@@ -1,13 +1,21 @@
 use std::{
     collections::{HashMap, HashSet},
     ffi::CString,
+    fmt,
     fs::read,
+    io::ErrorKind,
     num::NonZeroU32,
-    sync::mpsc::{Receiver, Sender, channel},
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        mpsc::{Receiver, Sender, channel},
+    },
     thread,
     time::{Duration, Instant},
 };
 
+use arboard::Clipboard;
 use gl::{GetIntegerv, GetnConvolutionFilter, types};
 use glutin::{
     config::{Config, ConfigTemplateBuilder},
@@ -22,31 +30,45 @@ use juste::{
     element::{Element, Message, SignalBus},
     genus::Src,
     io::{From, Io, On, Win},
-    style::{Font, Mode},
+    style::{ColorId, Font, Mode},
     util::Vec2,
 };
 use raw_window_handle::HasWindowHandle;
-use reqwest::blocking;
+use reqwest::{
+    StatusCode,
+    header::{IF_MODIFIED_SINCE, IF_NONE_MATCH},
+};
 use skia_safe::{
-    Color, Data, FontMgr, FontStyle, Image, Paint, TextBlob, Typeface,
+    CachingHint, Color, Data, FontMgr, FontStyle, GlyphId, Image, Paint, Point, Rect, TextBlob,
+    Typeface,
     gpu::{
-        DirectContext, Protected, SurfaceOrigin, backend_render_targets,
+        DirectContext, Mipmapped, Protected, SurfaceOrigin, backend_render_targets,
         ganesh::gl::direct_contexts,
         gl::{Format, FramebufferInfo, Interface},
         surfaces::wrap_backend_render_target,
     },
+    textlayout::{
+        FontCollection, Paragraph, RectHeightStyle, RectWidthStyle, TypefaceFontProvider,
+    },
 };
 use winit::{
     application::ApplicationHandler,
-    event::{ElementState, KeyEvent, MouseScrollDelta, StartCause, WindowEvent},
+    event::{ElementState, KeyEvent, MouseButton, MouseScrollDelta, StartCause, WindowEvent},
     event_loop::{ActiveEventLoop, ControlFlow, EventLoop, EventLoopProxy},
-    keyboard::PhysicalKey,
+    keyboard::{self, PhysicalKey},
     window::{Window, WindowAttributes, WindowId},
 };
 
 use crate::{
+    anim::Animation,
     app::App,
-    io::{filter_keyboard, filter_mouse},
+    disk::{Disk, Meta},
+    http::{Http, HttpConfig},
+    io::{Pointer, filter_keyboard, filter_mouse},
+    look::{Look, LookId},
+    svg::{self, Svg},
+    text::{self, Key, Layout, Rich, Selection, Shaped, Style},
+    uri,
 };
 
 pub fn run<T: App>(app: T, attr: WindowAttributes) {
@@ -77,6 +99,8 @@ pub fn run<T: App>(app: T, attr: WindowAttributes) {
             image: Images::new(),
             proxy,
             font: Fonts::new(),
+            pointer: Pointer::new(),
+            clipboard: None,
             window,
             gl_config,
         },
@@ -116,11 +140,135 @@ pub enum Pick<T> {
     One(T),
     All,
 }
+
+/// Where an image source currently stands, as seen by the app.
+pub enum Load<'a> {
+    Pending,
+    Ready(&'a Image),
+    Failed(&'a Fail),
+}
+
+/// Why an image could not be loaded.
+#[derive(Debug, Clone)]
+pub enum Fail {
+    Io(ErrorKind),
+    Decode,
+    Status(u16),
+    Network(String),
+    TooLarge,
+}
+
+impl fmt::Display for Fail {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Fail::Io(kind) => write!(f, "io error: {}", kind),
+            Fail::Decode => write!(f, "could not decode image"),
+            Fail::Status(code) => write!(f, "http status {}", code),
+            Fail::Network(err) => write!(f, "network error: {}", err),
+            Fail::TooLarge => write!(f, "response body is too large"),
+        }
+    }
+}
+
+/// Per-source fallback elements, used instead of `Image::fallback` when set.
+#[derive(Default)]
+pub struct Fallbacks {
+    pub pending: Option<fn(&Io) -> Element>,
+    pub failed: Option<fn(&Io, &Fail) -> Element>,
+}
+
+/// How long a failed source waits before it is fetched again.
+pub struct Backoff {
+    pub base: Duration,
+    pub max: Duration,
+    pub attempts: u32,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            base: Duration::from_millis(500),
+            max: Duration::from_secs(30),
+            attempts: 5,
+        }
+    }
+}
+
+impl Backoff {
+    pub fn delay(&self, attempts: u32) -> Duration {
+        let shift = attempts.saturating_sub(1).min(16);
+        self.base.saturating_mul(1 << shift).min(self.max)
+    }
+}
+
+/// What a worker hands back once a source is decoded.
+pub enum Asset {
+    Still(Image),
+    Animated(Animation),
+    /// Svg markup, parsed on the main thread since the dom can't be sent across.
+    Vector(Vec<u8>),
+}
+
+enum Named {
+    Bytes(Arc<[u8]>),
+    Image(Image),
+}
+
+struct Retry {
+    fail: Fail,
+    attempts: u32,
+    next: Instant,
+}
+
+/// A cached image and when it was last drawn.
+pub struct Entry {
+    pub image: Image,
+    pub bytes: usize,
+    /// Whether `image` is a texture with mip levels.
+    pub mipmapped: bool,
+    used: u64,
+}
+
+/// Counters for the image cache, readable from the `App` through `Cache::image`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Stats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    pub bytes: usize,
+}
+
 pub struct Images {
-    pub img: HashMap<Src, Image>,
-    pub sender: Sender<(Src, Image)>,
-    pub receiver: Receiver<(Src, Image)>,
+    pub img: HashMap<Src, Entry>,
+    /// Frames of animated sources, their `Entry` only keeps the first frame for bookkeeping.
+    pub anim: HashMap<Src, Animation>,
+    /// Svg sources, rasterized per drawn size by `Images::load_sized`.
+    pub svg: HashMap<Src, Svg>,
+    /// How images are sized and drawn, keyed by the pad id of their style so every element
+    /// with that pad shares a look whatever its source or color. Others stretch over their native size.
+    pub looks: HashMap<LookId, Look>,
+    /// Sources registered by the app, looked up by path or url before anything is fetched.
+    named: HashMap<String, Named>,
+    pub sender: Sender<(Src, Result<Asset, Fail>)>,
+    pub receiver: Receiver<(Src, Result<Asset, Fail>)>,
+    pub fallbacks: HashMap<Src, Fallbacks>,
+    pub backoff: Backoff,
+    /// Upper bound for decoded pixels in bytes, `None` means unbounded.
+    pub budget: Option<usize>,
+    pub stats: Stats,
+    /// On-disk cache for `Src::Url` images, see `Images::set_disk`.
+    pub disk: Option<Arc<Disk>>,
+    /// Shared client for `Src::Url` images, see `Images::set_http`.
+    /// `None` when the default client couldn't be built, urls then fail until one is set.
+    pub http: Option<Arc<Http>>,
     pending: HashSet<Src>,
+    failed: HashMap<Src, Retry>,
+    pinned: HashSet<Src>,
+    /// Sources drawn with `Sampling::Mipmap`, see `Images::mipmap`.
+    mips: HashSet<Src>,
+    fresh: Vec<Src>,
+    frame: u64,
+    wake: Option<Instant>,
 }
 
 impl Images {
@@ -128,161 +276,1167 @@ impl Images {
         let (sender, receiver) = channel();
         Self {
             img: HashMap::new(),
+            anim: HashMap::new(),
+            svg: HashMap::new(),
+            looks: HashMap::new(),
+            named: HashMap::new(),
             sender,
             receiver,
+            fallbacks: HashMap::new(),
+            backoff: Backoff::default(),
+            budget: None,
+            stats: Stats::default(),
+            disk: None,
+            http: Http::new(HttpConfig::default()).ok().map(Arc::new),
             pending: HashSet::new(),
+            failed: HashMap::new(),
+            pinned: HashSet::new(),
+            mips: HashSet::new(),
+            fresh: Vec::new(),
+            frame: 0,
+            wake: None,
         }
     }
 
     pub fn load(&mut self, name: &Src) -> Option<&Image> {
-        if !self.img.contains_key(name) {
-            match name {
-                Src::Sys(file) => match read(file) {
-                    Err(_) => return None,
-                    Ok(bytes) => {
-                        let data = Data::new_copy(&bytes);
-                        match Image::from_encoded(&data) {
-                            Some(img) => {
-                                self.img.insert(name.clone(), img);
-                            }
-                            None => return None,
-                        }
-                    }
-                },
-                Src::Url(url) => {
-                    if !self.pending.contains(name) {
-                        self.pending.insert(name.clone());
-                        load_url(url.to_string(), self.sender.clone());
-                    }
-                    match self.receiver.try_recv() {
-                        Ok((key, image)) => {
-                            self.pending.remove(&key);
-                            self.img.insert(key, image);
+        self.poll();
+        // A miss counts once, when its fetch is queued, and a hit once per frame it's drawn.
+        if !self.img.contains_key(name) && !self.pending.contains(name) && self.due(name) {
+            self.stats.misses += 1;
+            self.fetch(name);
+        }
+        let frame = self.frame;
+        self.img.get_mut(name).map(|entry| {
+            if entry.used < frame {
+                self.stats.hits += 1;
+            }
+            entry.used = frame;
+            self.anim.get(name).map_or(&entry.image, |a| a.image())
+        })
+    }
+
+    /// Serves `name` from bytes compiled into the binary, e.g. `include_bytes!("logo.png")`.
+    /// Both `Src::Sys(name)` and `Src::Url(name)` find it without touching the filesystem.
+    pub fn embed(&mut self, name: &str, bytes: &'static [u8]) {
+        self.register_named(name, Named::Bytes(Arc::from(bytes)));
+    }
+
+    /// Serves `name` from encoded bytes the app got at runtime.
+    pub fn register_bytes(&mut self, name: &str, bytes: Vec<u8>) {
+        self.register_named(name, Named::Bytes(Arc::from(bytes)));
+    }
+
+    /// Serves `name` from an image the app made itself, like a rendered chart.
+    /// Registering again under the same name replaces what was drawn before.
+    pub fn register(&mut self, name: &str, image: Image) {
+        self.register_named(name, Named::Image(image));
+    }
+
+    pub fn unregister(&mut self, name: &str) {
+        self.named.remove(name);
+        self.forget(name);
+    }
+
+    fn register_named(&mut self, name: &str, named: Named) {
+        self.named.insert(name.to_string(), named);
+        self.forget(name);
+    }
+
+    /// Drops whatever is cached for sources named `name`, so the next load picks up the new bytes.
+    fn forget(&mut self, name: &str) {
+        let stale: Vec<Src> = self
+            .img
+            .keys()
+            .chain(self.failed.keys())
+            .filter(|src| src_name(src) == name)
+            .cloned()
+            .collect();
+        for src in stale {
+            self.invalidate(Pick::One(&src));
+        }
+    }
+
+    /// Same as `load`, but svg sources come back rasterized at `dim`, in the same device pixels
+    /// as `Bound`. Each svg keeps a few recent sizes so a changing `Bound` doesn't re-render every frame.
+    pub fn load_sized(&mut self, name: &Src, dim: Vec2<f32>) -> Option<&Image> {
+        if !self.svg.contains_key(name) {
+            return self.load(name);
+        }
+        self.load(name)?;
+        // Also turns away NaN, an empty box has nothing to rasterize.
+        if !(dim.x > 0.0 && dim.y > 0.0) {
+            return None;
+        }
+        let svg = self.svg.get_mut(name)?;
+        let before = svg.bytes();
+        let (w, h) = (dim.x.round(), dim.y.round());
+        svg.raster(w as i32, h as i32)?;
+        let after = svg.bytes();
+        if let Some(entry) = self.img.get_mut(name) {
+            entry.bytes = entry.bytes + after - before;
+        }
+        self.stats.bytes = self.stats.bytes + after - before;
+        self.svg.get_mut(name)?.raster(w as i32, h as i32)
+    }
+
+    pub fn state(&self, name: &Src) -> Load<'_> {
+        match self.img.get(name) {
+            Some(entry) => Load::Ready(self.anim.get(name).map_or(&entry.image, |a| a.image())),
+            None => match self.failed.get(name) {
+                Some(retry) if !self.pending.contains(name) => Load::Failed(&retry.fail),
+                _ => Load::Pending,
+            },
+        }
+    }
+
+    /// Keeps downloaded images in `dir`, using at most `cap` bytes.
+    pub fn set_disk(&mut self, dir: impl Into<PathBuf>, cap: u64) {
+        self.disk = Some(Arc::new(Disk::new(dir, cap)));
+    }
+
+    /// Replaces the client, downloads already running finish with the old one.
+    pub fn set_http(&mut self, config: HttpConfig) -> Result<(), reqwest::Error> {
+        self.http = Some(Arc::new(Http::new(config)?));
+        Ok(())
+    }
+
+    /// Drains finished downloads into the cache.
+    pub fn poll(&mut self) {
+        while let Ok((name, result)) = self.receiver.try_recv() {
+            self.finish(name, result);
+        }
+    }
+
+    fn due(&self, name: &Src) -> bool {
+        match self.failed.get(name) {
+            None => true,
+            Some(retry) => retry.attempts < self.backoff.attempts && Instant::now() >= retry.next,
+        }
+    }
+
+    fn fetch(&mut self, name: &Src) {
+        match self.named.get(&src_name(name)) {
+            Some(Named::Image(image)) => {
+                let image = image.clone();
+                self.finish(name.clone(), Ok(Asset::Still(image)));
+                return;
+            }
+            Some(Named::Bytes(bytes)) => {
+                self.pending.insert(name.clone());
+                load_bytes(name.clone(), bytes.clone(), self.sender.clone());
+                return;
+            }
+            None => (),
+        }
+        match name {
+            Src::Sys(_) => {
+                self.pending.insert(name.clone());
+                load_file(name.clone(), self.sender.clone());
+            }
+            Src::Url(url) => {
+                self.pending.insert(name.clone());
+                load_url(
+                    url.to_string(),
+                    self.sender.clone(),
+                    self.http.clone(),
+                    self.disk.clone(),
+                );
+            }
+        }
+    }
+
+    fn finish(&mut self, name: Src, result: Result<Asset, Fail>) {
+        self.pending.remove(&name);
+        match result {
+            Ok(Asset::Still(image)) => {
+                self.failed.remove(&name);
+                let bytes = image.image_info().compute_min_byte_size();
+                self.fresh.push(name.clone());
+                self.insert(name, image, bytes);
+            }
+            Ok(Asset::Animated(animation)) => {
+                self.failed.remove(&name);
+                let first = animation.image().clone();
+                let bytes = animation.bytes();
+                self.anim.insert(name.clone(), animation);
+                self.insert(name, first, bytes);
+            }
+            Ok(Asset::Vector(bytes)) => match Svg::parse(&bytes) {
+                Some(mut svg) => {
+                    self.failed.remove(&name);
+                    let (w, h) = (svg.size.width.ceil(), svg.size.height.ceil());
+                    match svg.raster(w as i32, h as i32).cloned() {
+                        Some(first) => {
+                            let bytes = svg.bytes();
+                            self.svg.insert(name.clone(), svg);
+                            self.insert(name, first, bytes);
                         }
-                        Err(_) => (),
+                        None => self.finish(name, Err(Fail::Decode)),
                     }
                 }
+                None => self.finish(name, Err(Fail::Decode)),
+            },
+            Err(fail) => {
+                let attempts = self.failed.get(&name).map_or(0, |r| r.attempts) + 1;
+                let next = Instant::now() + self.backoff.delay(attempts);
+                self.failed.insert(
+                    name,
+                    Retry {
+                        fail,
+                        attempts,
+                        next,
+                    },
+                );
+            }
+        }
+    }
+
+    fn insert(&mut self, name: Src, image: Image, bytes: usize) {
+        let entry = Entry {
+            image,
+            bytes,
+            mipmapped: false,
+            used: self.frame,
+        };
+        self.stats.bytes += bytes;
+        if let Some(old) = self.img.insert(name, entry) {
+            self.stats.bytes -= old.bytes;
+        }
+        self.trim();
+    }
+
+    /// Asks for mip levels on `name`, built by the next `upload`.
+    /// Called while drawing an element whose `Look` samples with `Sampling::Mipmap`.
+    pub fn mipmap(&mut self, name: &Src) {
+        if !self.mips.contains(name) {
+            self.mips.insert(name.clone());
+        }
+    }
+
+    /// Moves images decoded since the last frame into GPU textures.
+    /// Sources passed to `Images::mipmap` get their mip levels built here,
+    /// including ones that were uploaded before they were asked for.
+    pub fn upload(&mut self, context: &mut DirectContext) {
+        let mut names: Vec<Src> = self.fresh.drain(..).collect();
+        names.extend(
+            self.mips
+                .iter()
+                .filter(|name| {
+                    !self.anim.contains_key(*name)
+                        && !self.svg.contains_key(*name)
+                        && self.img.get(*name).is_some_and(|e| !e.mipmapped)
+                })
+                .cloned(),
+        );
+        for name in names {
+            let mips = self.mips.contains(&name);
+            let Some(entry) = self.img.get_mut(&name) else {
+                continue;
+            };
+            let mode = match mips {
+                true => Mipmapped::Yes,
+                false => Mipmapped::No,
+            };
+            if let Some(texture) = entry.image.new_texture_image(context, mode) {
+                entry.image = texture;
+                if mips && !entry.mipmapped {
+                    // The whole mip chain is about a third on top of the base level.
+                    let extra = entry.bytes / 3;
+                    entry.bytes += extra;
+                    self.stats.bytes += extra;
+                    entry.mipmapped = true;
+                }
             }
         }
-        self.img.get(name)
+    }
+
+    pub fn animation(&mut self, name: &Src) -> Option<&mut Animation> {
+        self.anim.get_mut(name)
+    }
+
+    /// Advances every playing animation, returns when the next frame is due.
+    pub fn tick(&mut self, now: Instant) -> Option<Instant> {
+        self.wake = self
+            .anim
+            .values_mut()
+            .filter_map(|animation| animation.tick(now))
+            .min();
+        self.wake
+    }
+
+    /// When a playing animation wants the next redraw, as of the last `tick`.
+    pub fn next_frame(&self) -> Option<Instant> {
+        self.wake
+    }
+
+    /// Keeps `name` in memory regardless of the budget until `unpin` is called.
+    pub fn pin(&mut self, name: &Src) {
+        self.pinned.insert(name.clone());
+    }
+
+    pub fn unpin(&mut self, name: &Src) {
+        self.pinned.remove(name);
+    }
+
+    /// Evicts least recently drawn images until the cache fits the budget.
+    /// Pinned images and images drawn this frame are on screen and never evicted.
+    pub fn trim(&mut self) {
+        let Some(budget) = self.budget else {
+            return;
+        };
+        while self.stats.bytes > budget {
+            let victim = self
+                .img
+                .iter()
+                .filter(|(name, entry)| entry.used < self.frame && !self.pinned.contains(*name))
+                .min_by_key(|(_, entry)| entry.used)
+                .map(|(name, _)| name.clone());
+            match victim {
+                Some(name) => {
+                    self.remove(&name);
+                    self.stats.evictions += 1;
+                }
+                None => break,
+            }
+        }
+    }
+
+    /// Called once the frame is presented, everything loaded after this counts as the next frame.
+    pub fn end_frame(&mut self) {
+        self.trim();
+        self.frame += 1;
+    }
+
+    fn remove(&mut self, name: &Src) {
+        self.mips.remove(name);
+        self.anim.remove(name);
+        self.svg.remove(name);
+        if let Some(entry) = self.img.remove(name) {
+            self.stats.bytes -= entry.bytes;
+        }
     }
 
     pub fn invalidate(&mut self, pick: Pick<&Src>) {
         match pick {
-            Pick::All => self.img.clear(),
+            Pick::All => {
+                self.img.clear();
+                self.anim.clear();
+                self.svg.clear();
+                self.mips.clear();
+                self.failed.clear();
+                self.stats.bytes = 0;
+            }
             Pick::One(img) => {
-                self.img.remove(img);
+                self.remove(img);
+                self.failed.remove(img);
             }
         }
     }
 }
 
-fn load_url(url: String, sender: Sender<(Src, Image)>) {
+/// Decodes to a raster image right away, so the cost lands on the worker instead of the first draw.
+fn decode(bytes: &[u8]) -> Result<Asset, Fail> {
+    if svg::sniff(bytes) {
+        return Ok(Asset::Vector(bytes.to_vec()));
+    }
+    let data = Data::new_copy(bytes);
+    if let Some(animation) = Animation::decode(data.clone()) {
+        return Ok(Asset::Animated(animation));
+    }
+    Image::from_encoded(data)
+        .and_then(|lazy| lazy.make_raster_image(None, CachingHint::Disallow))
+        .map(Asset::Still)
+        .ok_or(Fail::Decode)
+}
+
+fn load_file(name: Src, sender: Sender<(Src, Result<Asset, Fail>)>) {
     thread::spawn(move || {
-        let req = blocking::get(&url);
-        match req {
-            Ok(response) => match response.bytes() {
-                Ok(b) => {
-                    let data = Data::new_copy(&b);
-                    let img = Image::from_encoded(data);
-                    if let Some(image) = img {
-                        let _ = sender.send((Src::Url(url), image));
-                    }
-                }
-                Err(_) => (),
+        let Src::Sys(file) = &name else {
+            return;
+        };
+        let result = read(file)
+            .map_err(|err| Fail::Io(err.kind()))
+            .and_then(|bytes| decode(&bytes));
+        let _ = sender.send((name, result));
+    });
+}
+
+fn load_bytes(name: Src, bytes: Arc<[u8]>, sender: Sender<(Src, Result<Asset, Fail>)>) {
+    thread::spawn(move || {
+        let _ = sender.send((name, decode(&bytes)));
+    });
+}
+
+/// Besides http(s), urls may be `data:` uris or `file://` paths.
+fn load_url(
+    url: String,
+    sender: Sender<(Src, Result<Asset, Fail>)>,
+    http: Option<Arc<Http>>,
+    disk: Option<Arc<Disk>>,
+) {
+    // Only network fetches wait for a worker, local ones don't hold up the queue.
+    let remote = !url.starts_with("data:") && uri::file_path(&url).is_none();
+    let pool = http.clone().filter(|_| remote);
+    let job = move || {
+        let bytes = match (url.starts_with("data:"), uri::file_path(&url)) {
+            (true, _) => uri::data(&url).ok_or(Fail::Decode),
+            (false, Some(path)) => read(path).map_err(|err| Fail::Io(err.kind())),
+            (false, None) => match &http {
+                Some(http) => download(&url, http, disk.as_deref()),
+                None => Err(Fail::Network("no http client".to_string())),
             },
-            Err(_) => (),
+        };
+        let result = bytes.and_then(|bytes| decode(&bytes));
+        let _ = sender.send((Src::Url(url), result));
+    };
+    match pool {
+        Some(pool) => pool.run(job),
+        None => {
+            thread::spawn(job);
         }
-    });
+    }
+}
+
+/// Fetches `url`, going through the disk cache when there is one.
+/// A fresh copy skips the network, a stale one is revalidated,
+/// and any copy at all is used when the server can't be reached.
+fn download(url: &str, http: &Http, disk: Option<&Disk>) -> Result<Vec<u8>, Fail> {
+    let cached = disk.and_then(|d| d.get(url));
+    if let Some((meta, body)) = &cached
+        && meta.fresh()
+    {
+        return Ok(body.clone());
+    }
+    let mut request = http.request(url);
+    if let Some((meta, _)) = &cached {
+        if let Some(etag) = &meta.etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(modified) = &meta.modified {
+            request = request.header(IF_MODIFIED_SINCE, modified);
+        }
+    }
+    let response = match request.send() {
+        Ok(response) => response,
+        Err(err) => {
+            return cached
+                .map(|(_, body)| body)
+                .ok_or(Fail::Network(err.to_string()));
+        }
+    };
+    match response.status() {
+        StatusCode::NOT_MODIFIED => {
+            let (old, body) = cached.ok_or(Fail::Status(304))?;
+            if let (Some(disk), Some(meta)) = (disk, Meta::from_headers(url, response.headers())) {
+                disk.touch(
+                    url,
+                    &Meta {
+                        etag: meta.etag.or(old.etag),
+                        modified: meta.modified.or(old.modified),
+                        ..meta
+                    },
+                );
+            }
+            Ok(body)
+        }
+        status if status.is_success() => {
+            let meta = Meta::from_headers(url, response.headers());
+            let body = http.read_body(response)?;
+            match (disk, meta) {
+                (Some(disk), Some(meta)) => disk.put(url, &meta, &body),
+                (Some(disk), None) => disk.remove(url),
+                _ => (),
+            }
+            Ok(body)
+        }
+        status => Err(Fail::Status(status.as_u16())),
+    }
+}
+
+/// Puts `text` on `clipboard`, opened on the first copy and kept for the rest of the run.
+/// On X11 and Wayland the copied text is served by whoever holds it, a dropped `Clipboard` loses it.
+fn copy(clipboard: &mut Option<Clipboard>, text: Option<String>) {
+    let Some(text) = text.filter(|t| !t.is_empty()) else {
+        return;
+    };
+    if clipboard.is_none() {
+        *clipboard = Clipboard::new().ok();
+    }
+    // Nothing to tell the user through, a failed copy leaves the clipboard as it was.
+    if let Some(clipboard) = clipboard {
+        let _ = clipboard.set_text(text);
+    }
+}
+
+/// What registered sources are looked up by.
+fn src_name(src: &Src) -> String {
+    match src {
+        Src::Sys(file) => Path::new(file).to_string_lossy().into_owned(),
+        Src::Url(url) => url.to_string(),
+    }
 }
 
 pub struct Fonts {
     pub font_mgr: FontMgr,
     pub fonts: HashMap<Font, FontAsset>,
+    /// Shapes `Genus::Text`, system fonts plus every `Font::File` under its alias.
+    pub collection: FontCollection,
+    provider: TypefaceFontProvider,
+    aliases: HashMap<Font, String>,
+    /// By the pad id of a text's style, see `Fonts::set_layout`.
+    layouts: HashMap<ColorId, Layout>,
+    /// By the pad id of a text's or input's style, see `Fonts::set_style`.
+    styles: HashMap<ColorId, Style>,
+    fallbacks: HashMap<Font, Vec<Font>>,
+    /// Fonts that didn't load, kept so they aren't read again every frame.
+    failed: HashMap<Font, FontError>,
+    /// Failures not yet handed to `App::font_failed`, each font once.
+    failures: Vec<(Font, FontError)>,
+    /// Rich texts by the pad id of the texts showing them, see `Fonts::set_rich`.
+    rich: HashMap<ColorId, Rich>,
+    /// Where each rich text was last painted, for `hit`.
+    placed: HashMap<ColorId, (Key, Point)>,
+    pub selection: Option<Selection>,
+    /// Drawn behind selected text.
+    pub highlight: Color,
+    /// Color emoji family at the end of every chain, changes apply to fonts loaded afterwards.
+    pub emoji: Option<String>,
+    paragraphs: HashMap<Key, Shaped>,
+    frame: u64,
+}
+
+/// Why a `Font` didn't load.
+#[derive(Debug, Clone)]
+pub enum FontError {
+    /// The font file couldn't be read.
+    Io(PathBuf, ErrorKind),
+    Decode,
+    /// No installed family by this name.
+    Missing(String),
 }
 
+impl fmt::Display for FontError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FontError::Io(path, kind) => write!(f, "could not read {}: {}", path.display(), kind),
+            FontError::Decode => write!(f, "could not decode font"),
+            FontError::Missing(name) => write!(f, "no family named {}", name),
+        }
+    }
+}
+
+impl std::error::Error for FontError {}
+
+/// An installed family, each style with the name the font gives it.
+pub struct Family {
+    pub name: String,
+    pub styles: Vec<(String, FontStyle)>,
+}
+
+/// A shaped token, one blob per fallback face with its offset from the token's start.
+pub struct Token {
+    pub runs: Vec<(TextBlob, f32)>,
+    pub width: f32,
+    /// Estimate of what the token holds, see `TokenStats::bytes`.
+    pub bytes: usize,
+    used: bool,
+}
+
+/// Counters for the token cache of a `FontAsset`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TokenStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    /// Estimated from the glyphs of every blob, Skia keeps a glyph id and a position per glyph.
+    pub bytes: usize,
+}
+
+/// What Skia keeps per `TextBlob` besides its glyphs, the blob and its run record, roughly.
+const BLOB_HEADER: usize = 96;
+
 pub struct FontAsset {
     pub tf: Typeface,
     pub font: skia_safe::Font,
-    pub atlas: HashMap<char, TextBlob>,
+    /// Input tokens already turned into blobs, by their text.
+    /// Lines `Dirt` re-highlights come back as new tokens and the old ones are swept once undrawn.
+    pub tokens: HashMap<String, Token>,
+    pub stats: TokenStats,
+    /// Tried in order when `font` has no glyph, the `Fonts::fallbacks` chain then the emoji font.
+    pub chain: Vec<skia_safe::Font>,
+    /// What the system fell back to, per block of 128 codepoints.
+    ranges: HashMap<u32, Option<skia_safe::Font>>,
+    mgr: FontMgr,
+    style: FontStyle,
 }
 
 impl FontAsset {
-    pub fn get_char(&mut self, char: &char) -> Option<&TextBlob> {
-        if !self.atlas.contains_key(char) {
-            TextBlob::from_str(char.to_string(), &self.font)
-                .map(|blob| self.atlas.insert(*char, blob));
+    /// Blobs for `text`, built the first time it is drawn.
+    pub fn token(&mut self, text: &str) -> &Token {
+        match self.tokens.contains_key(text) {
+            true => self.stats.hits += 1,
+            false => {
+                self.stats.misses += 1;
+                let mut runs = Vec::new();
+                let mut width = 0.0;
+                let mut bytes = size_of::<Token>() + text.len();
+                for (face, range) in self.runs(text) {
+                    let run = &text[range];
+                    if let Some(blob) = TextBlob::from_str(run, &face) {
+                        let glyphs = face.count_str(run);
+                        bytes += size_of::<(TextBlob, f32)>()
+                            + BLOB_HEADER
+                            + glyphs * (size_of::<GlyphId>() + size_of::<Point>());
+                        runs.push((blob, width));
+                    }
+                    width += face.measure_str(run, None).0;
+                }
+                self.stats.bytes += bytes;
+                self.tokens.insert(
+                    text.to_string(),
+                    Token {
+                        runs,
+                        width,
+                        bytes,
+                        used: false,
+                    },
+                );
+            }
         }
-        self.atlas.get(char)
+        let token = self.tokens.get_mut(text).expect("token was just inserted");
+        token.used = true;
+        token
+    }
+
+    /// Advance of `text` across its fallback faces, without building blobs.
+    pub fn measure(&mut self, text: &str) -> f32 {
+        if let Some(token) = self.tokens.get(text) {
+            return token.width;
+        }
+        self.runs(text)
+            .into_iter()
+            .map(|(face, range)| face.measure_str(&text[range], None).0)
+            .sum()
+    }
+
+    /// Drops tokens nothing drew since the last sweep.
+    pub fn sweep(&mut self) {
+        let stats = &mut self.stats;
+        self.tokens.retain(|_, token| {
+            if !token.used {
+                stats.evictions += 1;
+                stats.bytes -= token.bytes;
+            }
+            std::mem::take(&mut token.used)
+        });
+    }
+
+    /// Drops every token, for when what they were built from changed.
+    pub fn clear(&mut self) {
+        self.stats.evictions += self.tokens.len() as u64;
+        self.stats.bytes = 0;
+        self.tokens.clear();
+    }
+
+    /// First face with a glyph for `c`, `font` when none has one.
+    pub fn face(&mut self, c: char) -> &skia_safe::Font {
+        let uni = c as i32;
+        if c.is_control() || self.font.unichar_to_glyph(uni) != 0 {
+            return &self.font;
+        }
+        if let Some(i) = self.chain.iter().position(|f| f.unichar_to_glyph(uni) != 0) {
+            return &self.chain[i];
+        }
+        let block = c as u32 >> 7;
+        // A block the system had nothing for stays `None`, one it matched for another char is asked again.
+        let stale = match self.ranges.get(&block) {
+            None => true,
+            Some(face) => face.as_ref().is_some_and(|f| f.unichar_to_glyph(uni) == 0),
+        };
+        if stale {
+            let face = self
+                .mgr
+                .match_family_style_character("", self.style, &[], uni)
+                .map(|tf| skia_safe::Font::from_typeface(tf, Some(self.font.size())));
+            self.ranges.insert(block, face);
+        }
+        match self.ranges.get(&block) {
+            Some(Some(face)) if face.unichar_to_glyph(uni) != 0 => face,
+            _ => &self.font,
+        }
+    }
+
+    /// Splits `text` into byte ranges drawn with the same face.
+    pub fn runs(&mut self, text: &str) -> Vec<(skia_safe::Font, Range<usize>)> {
+        let mut runs: Vec<(skia_safe::Font, Range<usize>)> = Vec::new();
+        for (i, c) in text.char_indices() {
+            let face = self.face(c);
+            let end = i + c.len_utf8();
+            match runs.last_mut() {
+                Some((last, range))
+                    if last.typeface().unique_id() == face.typeface().unique_id() =>
+                {
+                    range.end = end
+                }
+                _ => runs.push((face.clone(), i..end)),
+            }
+        }
+        runs
     }
 }
 
 impl Fonts {
     pub fn new() -> Self {
         let font_mgr = FontMgr::new();
+        let provider = TypefaceFontProvider::new();
+        let mut collection = FontCollection::new();
+        collection.set_default_font_manager(font_mgr.clone(), None);
+        collection.set_asset_font_manager(Some(provider.clone().into()));
+        collection.enable_font_fallback();
         Self {
             font_mgr,
             fonts: HashMap::new(),
+            collection,
+            provider,
+            aliases: HashMap::new(),
+            layouts: HashMap::new(),
+            styles: HashMap::new(),
+            fallbacks: HashMap::new(),
+            failed: HashMap::new(),
+            failures: Vec::new(),
+            rich: HashMap::new(),
+            placed: HashMap::new(),
+            selection: None,
+            highlight: Color::from_argb(96, 51, 144, 255),
+            emoji: Some(emoji_family().to_string()),
+            paragraphs: HashMap::new(),
+            frame: 0,
         }
     }
 
-    pub fn load_asset(&mut self, font: &Font) -> Option<&mut FontAsset> {
-        if !self.fonts.contains_key(font) {
-            match font {
-                Font::File { path, ttc, .. } => match read(path) {
-                    Err(_) => None,
-                    Ok(file) => {
-                        let data = Data::new_copy(&file);
-                        self.font_mgr
-                            .new_from_data(&data, Some(*ttc))
-                            .and_then(|tf| {
-                                let tf_font = skia_safe::Font::from_typeface(
-                                    &tf,
-                                    Some(font.get_size() as f32),
-                                );
-                                self.fonts.insert(
-                                    *font,
-                                    FontAsset {
-                                        tf,
-                                        font: tf_font,
-                                        atlas: HashMap::new(),
-                                    },
-                                )
-                            })
-                    }
+    pub fn layout(&self, pad: &ColorId) -> Layout {
+        self.layouts.get(pad).copied().unwrap_or_default()
+    }
+
+    /// Line breaking for every `Genus::Text` whose style has the pad id `pad`,
+    /// whatever font or color it has. Give a text its own pad entry to give it its own layout.
+    pub fn set_layout(&mut self, pad: ColorId, layout: Layout) {
+        self.layouts.insert(pad, layout);
+        self.paragraphs.retain(|key, _| key.pad != pad);
+    }
+
+    pub fn style(&self, pad: &ColorId) -> Style {
+        self.styles.get(pad).cloned().unwrap_or_default()
+    }
+
+    /// Decoration, spacing and features for every `Genus::Text` and `Genus::Input` whose style
+    /// has the pad id `pad`, whatever font it has. Input tokens don't depend on it, they are kept.
+    pub fn set_style(&mut self, pad: ColorId, style: Style) {
+        self.styles.insert(pad, style);
+        self.paragraphs.retain(|key, _| key.pad != pad);
+    }
+
+    /// Shaped `text`, laid out again only when `width` changed since last time.
+    /// A NaN `width` keeps the last one, for callers that only need the intrinsic widths.
+    pub fn paragraph(
+        &mut self,
+        font: &Font,
+        pad: &ColorId,
+        text: &str,
+        color: Color,
+        width: f32,
+    ) -> Option<&mut Paragraph> {
+        let key = Key {
+            font: *font,
+            pad: *pad,
+            text: text.to_string(),
+            color: color.into(),
+        };
+        let layout = self.layout(pad);
+        if !self.paragraphs.contains_key(&key) {
+            let style = self.style(pad);
+            let runs = match self.rich.get(pad).cloned() {
+                Some(rich) => text::span_styles(&rich, &style, |span| {
+                    let f = span.font.unwrap_or(*font);
+                    let size = span.size.unwrap_or(f.get_size() as f32);
+                    let families = self.families(&f)?;
+                    let col = span.color.unwrap_or(color);
+                    Some(text::text_style(
+                        &families,
+                        size,
+                        style_of(&f),
+                        col,
+                        &layout,
+                    ))
+                })?,
+                None => {
+                    let families = self.families(font)?;
+                    let size = font.get_size() as f32;
+                    let mut text_style =
+                        text::text_style(&families, size, style_of(font), color, &layout);
+                    style.apply(&mut text_style);
+                    vec![(text_style, text.to_string())]
+                }
+            };
+            let paragraph = text::shape(&self.collection, &layout, &runs);
+            self.paragraphs.insert(
+                key.clone(),
+                Shaped {
+                    paragraph,
+                    width: f32::NAN,
+                    used: 0,
                 },
-                Font::Sys { name, mode, .. } => self
-                    .font_mgr
-                    .match_family_style(name, font_style(mode))
-                    .and_then(|tf| {
-                        let tf_font =
-                            skia_safe::Font::from_typeface(&tf, Some(font.get_size() as f32));
-                        self.fonts.insert(
-                            *font,
-                            FontAsset {
-                                tf,
-                                font: tf_font,
-                                atlas: HashMap::new(),
-                            },
-                        )
-                    }),
+            );
+        }
+        let frame = self.frame;
+        let shaped = self.paragraphs.get_mut(&key)?;
+        shaped.used = frame;
+        let width = match (width.is_nan(), shaped.width.is_nan()) {
+            (true, true) => f32::INFINITY,
+            (true, false) => shaped.width,
+            (false, _) => width,
+        };
+        if shaped.width != width {
+            text::lay_out(&mut shaped.paragraph, &layout, width);
+            shaped.width = width;
+        }
+        Some(&mut shaped.paragraph)
+    }
+
+    /// `font`'s family, its fallback chain, then the emoji font.
+    fn families(&mut self, font: &Font) -> Option<Vec<String>> {
+        let mut families = vec![self.family(font)?];
+        let chain = self.fallbacks.get(font).cloned().unwrap_or_default();
+        families.extend(chain.iter().filter_map(|f| self.family(f)));
+        families.extend(self.emoji.clone());
+        Some(families)
+    }
+
+    /// Shows `rich` instead of their own text in every `Genus::Text` whose style has the pad id `pad`.
+    /// Give the text a pad entry of its own, its layout is also looked up by it.
+    pub fn set_rich(&mut self, pad: ColorId, rich: Rich) {
+        self.rich.insert(pad, rich);
+        self.paragraphs.retain(|key, _| key.pad != pad);
+    }
+
+    pub fn remove_rich(&mut self, pad: &ColorId) {
+        self.rich.remove(pad);
+        self.placed.remove(pad);
+        self.paragraphs.retain(|key, _| key.pad != *pad);
+    }
+
+    /// Whether texts whose style has the pad id `pad` show a rich text.
+    pub fn is_rich(&self, pad: &ColorId) -> bool {
+        self.rich.contains_key(pad)
+    }
+
+    /// Remembers where the rich text of `key.pad` was painted.
+    pub fn place(&mut self, key: Key, origin: Point) {
+        if self.rich.contains_key(&key.pad) {
+            self.placed.insert(key.pad, (key, origin));
+        }
+    }
+
+    /// Span and character index under `point`, in window coordinates, of the rich text
+    /// registered under `pad` as last painted. Rotation is not accounted for.
+    pub fn hit(&self, pad: &ColorId, point: Point) -> Option<(usize, usize)> {
+        let (key, origin) = self.placed.get(pad)?;
+        let shaped = self.paragraphs.get(key)?;
+        let at = shaped
+            .paragraph
+            .get_glyph_position_at_coordinate(point - *origin);
+        text::locate(
+            self.rich.get(pad)?,
+            at.position,
+            at.affinity,
+            &self.layout(&key.pad),
+        )
+    }
+
+    /// Starts, extends or keeps the selection in the text `key` painted at `origin` inside `bounds`,
+    /// returns the highlight to draw in window coordinates.
+    pub fn select(
+        &mut self,
+        key: &Key,
+        origin: Point,
+        bounds: Rect,
+        pointer: &Pointer,
+    ) -> Vec<Rect> {
+        let Some(shaped) = self.paragraphs.get(key) else {
+            return Vec::new();
+        };
+        let paragraph = &shaped.paragraph;
+        let local = pointer.pos - origin;
+        let at = paragraph
+            .get_glyph_position_at_coordinate(local)
+            .position
+            .max(0) as usize;
+        let mine = |s: &Selection| s.key == *key && s.origin == origin;
+        let dragging =
+            pointer.down && pointer.clicks == 1 && self.selection.as_ref().is_some_and(mine);
+        if pointer.pressed && bounds.contains(pointer.pos) {
+            let range = match pointer.clicks {
+                1 => at..at,
+                2 => paragraph.get_word_boundary(at as u32),
+                _ => text::line_at(paragraph, local.y),
             };
+            self.selection = Some(Selection {
+                key: key.clone(),
+                origin,
+                anchor: range.start,
+                focus: range.end,
+            });
+        } else if dragging {
+            if let Some(selection) = self.selection.as_mut() {
+                selection.focus = at;
+            }
+        }
+        match &self.selection {
+            Some(s) if mine(s) && s.anchor != s.focus => paragraph
+                .get_rects_for_range(s.range(), RectHeightStyle::Max, RectWidthStyle::Tight)
+                .iter()
+                .map(|b| b.rect.with_offset(origin))
+                .collect(),
+            _ => Vec::new(),
         }
-        self.fonts.get_mut(font)
+    }
+
+    /// The selected text, without anything shaping added.
+    pub fn selected(&self) -> Option<String> {
+        let selection = self.selection.as_ref()?;
+        let layout = self.layout(&selection.key.pad);
+        let source: String = match self.rich.get(&selection.key.pad) {
+            Some(rich) => rich
+                .spans
+                .iter()
+                .map(|span| text::shown(&span.text, &layout))
+                .collect(),
+            None => text::shown(&selection.key.text, &layout).into_owned(),
+        };
+        let range = selection.range();
+        let start = text::byte_index(&source, range.start);
+        let end = text::byte_index(&source, range.end);
+        Some(
+            source[start..end]
+                .chars()
+                .filter(|c| !text::inserted(*c, &layout))
+                .collect(),
+        )
+    }
+
+    /// Family name the collection knows `font` by, file fonts are registered on first use.
+    fn family(&mut self, font: &Font) -> Option<String> {
+        match font {
+            Font::Sys { name, .. } => Some(name.to_string()),
+            Font::File { path, ttc, .. } => {
+                if let Some(alias) = self.aliases.get(font) {
+                    return Some(alias.clone());
+                }
+                let alias = format!("{}#{}", Path::new(path).display(), ttc);
+                let tf = self.load_asset(font).ok()?.tf.clone();
+                self.provider.register_typeface(tf, alias.as_str());
+                self.aliases.insert(*font, alias.clone());
+                Some(alias)
+            }
+        }
+    }
+
+    /// Drops paragraphs that weren't drawn last frame.
+    pub fn end_frame(&mut self) {
+        let frame = self.frame;
+        self.paragraphs.retain(|_, shaped| shaped.used + 1 >= frame);
+        self.fonts.values_mut().for_each(FontAsset::sweep);
+        self.frame += 1;
+    }
+
+    pub fn load_asset(&mut self, font: &Font) -> Result<&mut FontAsset, FontError> {
+        if let Some(err) = self.failed.get(font) {
+            return Err(err.clone());
+        }
+        if !self.fonts.contains_key(font) {
+            let tf = self.typeface(font).inspect_err(|err| {
+                self.failed.insert(*font, err.clone());
+                self.failures.push((*font, err.clone()));
+            })?;
+            let size = font.get_size() as f32;
+            let chain = self.chain(font);
+            self.fonts.insert(
+                *font,
+                FontAsset {
+                    font: skia_safe::Font::from_typeface(tf.clone(), Some(size)),
+                    tf,
+                    tokens: HashMap::new(),
+                    stats: TokenStats::default(),
+                    chain,
+                    ranges: HashMap::new(),
+                    mgr: self.font_mgr.clone(),
+                    style: style_of(font),
+                },
+            );
+        }
+        Ok(self.fonts.get_mut(font).expect("asset was just inserted"))
+    }
+
+    /// Faces an input in `font` falls back to, its `fallbacks` then the emoji font.
+    fn chain(&self, font: &Font) -> Vec<skia_safe::Font> {
+        let size = font.get_size() as f32;
+        let mut chain: Vec<_> = self
+            .fallbacks
+            .get(font)
+            .into_iter()
+            .flatten()
+            .filter_map(|f| self.typeface(f).ok())
+            .collect();
+        chain.extend(
+            self.emoji
+                .as_ref()
+                .and_then(|name| self.font_mgr.match_family_style(name, FontStyle::normal())),
+        );
+        chain
+            .into_iter()
+            .map(|tf| skia_safe::Font::from_typeface(tf, Some(size)))
+            .collect()
+    }
+
+    /// `font` when it loads, else `default`. Why it didn't is kept for `Fonts::error`
+    /// and handed to `App::font_failed` once.
+    pub fn resolve(&mut self, font: &Font, default: &Font) -> Font {
+        match self.load_asset(font) {
+            Ok(_) => *font,
+            Err(_) => *default,
+        }
+    }
+
+    /// Why `font` didn't load, `None` when it did or wasn't tried yet.
+    pub fn error(&self, font: &Font) -> Option<&FontError> {
+        self.failed.get(font)
+    }
+
+    /// Fonts that failed to load since the last call, each once until it is invalidated.
+    pub fn take_failures(&mut self) -> Vec<(Font, FontError)> {
+        std::mem::take(&mut self.failures)
+    }
+
+    /// Every family the system has, with the styles it comes in, for font pickers.
+    pub fn system_families(&self) -> Vec<Family> {
+        let mut families = Vec::with_capacity(self.font_mgr.count_families());
+        for i in 0..self.font_mgr.count_families() {
+            let mut set = self.font_mgr.new_style_set(i);
+            let styles = (0..set.count())
+                .map(|j| {
+                    let (style, name) = set.style(j);
+                    (name.unwrap_or_default(), style)
+                })
+                .collect();
+            families.push(Family {
+                name: self.font_mgr.family_name(i),
+                styles,
+            });
+        }
+        families.sort_by(|a, b| a.name.cmp(&b.name));
+        families
+    }
+
+    fn typeface(&self, font: &Font) -> Result<Typeface, FontError> {
+        match font {
+            Font::File { path, ttc, .. } => {
+                let file =
+                    read(path).map_err(|err| FontError::Io(Path::new(path).into(), err.kind()))?;
+                self.font_mgr
+                    .new_from_data(&Data::new_copy(&file), Some(*ttc))
+                    .ok_or(FontError::Decode)
+            }
+            // Registered families come first, so an alias can stand in for a system family.
+            Font::Sys { name, mode, .. } => self
+                .provider
+                .match_family_style(name, font_style(mode))
+                .or_else(|| self.font_mgr.match_family_style(name, font_style(mode)))
+                .ok_or_else(|| FontError::Missing(name.to_string())),
+        }
+    }
+
+    /// Registers a font compiled into the binary, e.g. `include_bytes!("Inter.ttf")`,
+    /// so `Font::Sys { name: alias, .. }` finds it like a system family.
+    /// Faces registered under the same alias are told apart by `Mode`.
+    pub fn embed(&mut self, alias: &str, bytes: &'static [u8]) -> Result<(), FontError> {
+        // Static bytes outlive any `Data` pointing at them.
+        self.register_data(alias, unsafe { Data::new_bytes(bytes) })
+    }
+
+    /// Registers a font the app got at runtime, see `embed`.
+    pub fn register_bytes(&mut self, alias: &str, bytes: Vec<u8>) -> Result<(), FontError> {
+        self.register_data(alias, Data::new_copy(&bytes))
+    }
+
+    fn register_data(&mut self, alias: &str, data: Data) -> Result<(), FontError> {
+        let tf = self
+            .font_mgr
+            .new_from_data(&data, None)
+            .ok_or(FontError::Decode)?;
+        self.provider.register_typeface(tf, alias);
+        // Whatever looked the alias up before, and fell back, does it again.
+        self.collection.clear_caches();
+        self.fonts.retain(|font, _| describe(font) != alias);
+        self.failed.retain(|font, _| describe(font) != alias);
+        self.paragraphs
+            .retain(|key, _| describe(&key.font) != alias);
+        Ok(())
+    }
+
+    /// Fonts tried in order for glyphs `font` doesn't have, before the emoji font and the system.
+    pub fn set_fallbacks(&mut self, font: Font, chain: Vec<Font>) {
+        self.fallbacks.insert(font, chain);
+        if self.fonts.contains_key(&font) {
+            let chain = self.chain(&font);
+            if let Some(asset) = self.fonts.get_mut(&font) {
+                asset.chain = chain;
+                asset.clear();
+            }
+        }
+        self.paragraphs.retain(|key, _| key.font != font);
     }
 
     pub fn invalidate(&mut self, font: Pick<&Font>) {
         match font {
             Pick::All => {
                 self.fonts.clear();
+                self.failed.clear();
+                self.paragraphs.clear();
             }
             Pick::One(f) => {
                 self.fonts.remove(f);
+                self.failed.remove(f);
+                self.paragraphs.retain(|key, _| key.font != *f);
             }
         }
     }
 }
 
+fn emoji_family() -> &'static str {
+    if cfg!(windows) {
+        "Segoe UI Emoji"
+    } else if cfg!(target_os = "macos") {
+        "Apple Color Emoji"
+    } else {
+        "Noto Color Emoji"
+    }
+}
+
+fn describe(font: &Font) -> String {
+    match font {
+        Font::Sys { name, .. } => name.to_string(),
+        Font::File { path, .. } => Path::new(path).display().to_string(),
+    }
+}
+
+/// File fonts are a single face, whatever style it has.
+fn style_of(font: &Font) -> FontStyle {
+    match font {
+        Font::Sys { mode, .. } => font_style(mode),
+        Font::File { .. } => FontStyle::normal(),
+    }
+}
+
 fn font_style(mode: &Mode) -> FontStyle {
     match mode {
         Mode::Normal => FontStyle::normal(),
@@ -299,6 +1453,9 @@ pub struct Cache {
     pub image: Images,
     pub proxy: EventLoopProxy<Message>,
     pub font: Fonts,
+    pub pointer: Pointer,
+    /// `None` until something is copied, see `copy`.
+    pub clipboard: Option<Clipboard>,
     pub window: Window,
     pub gl_config: Config,
 }
@@ -334,10 +1491,26 @@ impl<T: App> Renderer<T> {
     fn draw(&mut self) {
         match self.graphic.as_mut() {
             Some(graphic) => {
+                self.cache.image.poll();
+                self.cache.image.upload(&mut graphic.gr_context);
+                self.cache.image.tick(Instant::now());
                 let canvas = graphic.sk_surface.canvas();
                 let col = Color::from_argb(255, 0, 0, 0);
                 canvas.clear(col);
+                // A press lands on a selectable text, which selects again, or clears the selection.
+                if self.cache.pointer.pressed {
+                    self.cache.font.selection = None;
+                }
                 self.app.draw(&mut self.cache, canvas);
+                for (font, error) in self.cache.font.take_failures() {
+                    self.app.font_failed(&font, &error, &mut self.cache);
+                }
+                if self.cache.pointer.copy {
+                    copy(&mut self.cache.clipboard, self.cache.font.selected());
+                }
+                self.cache.pointer.end_frame();
+                self.cache.image.end_frame();
+                self.cache.font.end_frame();
                 graphic.gr_context.flush_and_submit();
                 graphic.gl_surface.swap_buffers(&graphic.context).unwrap();
                 while let Some(msg) = self.cache.io.bus.queue.pop() {
@@ -441,9 +1614,12 @@ impl<T: App> ApplicationHandler<Message> for Renderer<T> {
         match cause {
             StartCause::ResumeTimeReached { .. } | StartCause::Init => {
                 self.cache.window.request_redraw();
-                event_loop.set_control_flow(ControlFlow::WaitUntil(
-                    Instant::now() + Duration::from_millis(16),
-                ));
+                let mut wake = Instant::now() + Duration::from_millis(16);
+                // Animations with shorter frames than that need to be woken up sooner.
+                if let Some(next) = self.cache.image.next_frame() {
+                    wake = wake.min(next);
+                }
+                event_loop.set_control_flow(ControlFlow::WaitUntil(wake));
             }
             _ => (),
         }
@@ -462,7 +1638,7 @@ impl<T: App> ApplicationHandler<Message> for Renderer<T> {
             } => match event {
                 KeyEvent {
                     physical_key,
-                    logical_key: _,
+                    logical_key,
                     text: _,
                     location: _,
                     state,
@@ -470,6 +1646,14 @@ impl<T: App> ApplicationHandler<Message> for Renderer<T> {
                     ..
                 } => match physical_key {
                     PhysicalKey::Code(key) => {
+                        // By the character, so the shortcut follows the keyboard layout.
+                        let c = match &logical_key {
+                            keyboard::Key::Character(c) => c.eq_ignore_ascii_case("c"),
+                            _ => false,
+                        };
+                        if c && state == ElementState::Pressed && self.cache.pointer.command {
+                            self.cache.pointer.copy = true;
+                        }
                         let k = filter_keyboard(key);
                         let input = match state {
                             ElementState::Pressed => On::Press(From::Key(k)),
@@ -486,6 +1670,12 @@ impl<T: App> ApplicationHandler<Message> for Renderer<T> {
                 state,
                 button,
             } => {
+                if button == MouseButton::Left {
+                    match state {
+                        ElementState::Pressed => self.cache.pointer.press(Instant::now()),
+                        ElementState::Released => self.cache.pointer.release(),
+                    }
+                }
                 let b = filter_mouse(button);
                 let m = match state {
                     ElementState::Pressed => On::Press(From::Mouse(b)),
@@ -493,6 +1683,16 @@ impl<T: App> ApplicationHandler<Message> for Renderer<T> {
                 };
                 self.cache.io.pool(m);
             }
+            WindowEvent::CursorMoved { position, .. } => {
+                self.cache.pointer.pos = Point::new(position.x as f32, position.y as f32);
+            }
+            WindowEvent::ModifiersChanged(modifiers) => {
+                let state = modifiers.state();
+                self.cache.pointer.command = match cfg!(target_os = "macos") {
+                    true => state.super_key(),
+                    false => state.control_key(),
+                };
+            }
             WindowEvent::Resized(size) => {
                 self.cache.io.window_size = Vec2::new(size.width as f32, size.height as f32);
                 self.cache.io.pool(On::Window(Win::Resize));
@@ -526,3 +1726,173 @@ impl<T: App> ApplicationHandler<Message> for Renderer<T> {
         self.app.user_event(event, &mut self.cache, event_loop);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+        thread::JoinHandle,
+    };
+
+    use super::*;
+
+    /// Answers one connection per response on a local port, then hands back what was asked.
+    fn serve(responses: Vec<&'static str>) -> (String, JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("Could not bind a local port");
+        let url = format!("http://{}/a.png", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            responses
+                .into_iter()
+                .map(|response| {
+                    let (mut stream, _) = listener.accept().unwrap();
+                    let mut request = Vec::new();
+                    let mut buf = [0; 1024];
+                    while !request.ends_with(b"\r\n\r\n") {
+                        match stream.read(&mut buf).unwrap() {
+                            0 => break,
+                            n => request.extend_from_slice(&buf[..n]),
+                        }
+                    }
+                    stream.write_all(response.as_bytes()).unwrap();
+                    String::from_utf8_lossy(&request).to_lowercase()
+                })
+                .collect()
+        });
+        (url, server)
+    }
+
+    #[test]
+    fn download_revalidates_and_falls_back_to_disk() {
+        let dir = std::env::temp_dir().join(format!("juste_skia_download_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let disk = Disk::new(&dir, 1 << 20);
+        let http = Http::new(HttpConfig::default()).unwrap();
+        let (url, server) = serve(vec![
+            "HTTP/1.1 200 OK\r\nETag: \"v1\"\r\nCache-Control: max-age=0\r\nContent-Length: 3\r\nConnection: close\r\n\r\none",
+            "HTTP/1.1 304 Not Modified\r\nETag: \"v1\"\r\nConnection: close\r\n\r\n",
+        ]);
+        assert_eq!(download(&url, &http, Some(&disk)).unwrap(), b"one");
+        assert_eq!(download(&url, &http, Some(&disk)).unwrap(), b"one");
+        let requests = server.join().unwrap();
+        assert!(!requests[0].contains("if-none-match"));
+        assert!(requests[1].contains("if-none-match: \"v1\""));
+        // The stand-in is gone, the stale copy is still served.
+        assert_eq!(download(&url, &http, Some(&disk)).unwrap(), b"one");
+        assert!(download(&url, &http, None).is_err());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn download_stops_at_max_body() {
+        let http = Http::new(HttpConfig {
+            max_body: 2,
+            ..HttpConfig::default()
+        })
+        .unwrap();
+        let (url, server) = serve(vec![
+            "HTTP/1.1 200 OK\r\nContent-Length: 3\r\nConnection: close\r\n\r\none",
+        ]);
+        assert!(matches!(download(&url, &http, None), Err(Fail::TooLarge)));
+        server.join().unwrap();
+    }
+
+    /// An asset over the system's default face, with no fallbacks.
+    fn asset() -> FontAsset {
+        let mgr = FontMgr::new();
+        let tf = mgr
+            .legacy_make_typeface(None, FontStyle::normal())
+            .expect("No default typeface");
+        FontAsset {
+            font: skia_safe::Font::from_typeface(tf.clone(), Some(14.0)),
+            tf,
+            tokens: HashMap::new(),
+            stats: TokenStats::default(),
+            chain: Vec::new(),
+            ranges: HashMap::new(),
+            mgr,
+            style: FontStyle::normal(),
+        }
+    }
+
+    #[test]
+    fn token_stats_follow_sweep_and_clear() {
+        let mut font = asset();
+        font.token("let");
+        font.token("x");
+        font.token("let");
+        assert_eq!((font.stats.hits, font.stats.misses), (1, 2));
+        let held = |font: &FontAsset| font.tokens.values().map(|t| t.bytes).sum::<usize>();
+        assert_eq!(font.stats.bytes, held(&font));
+        assert!(font.stats.bytes > 2 * size_of::<Token>());
+        // Measuring a cached token neither counts nor marks it drawn.
+        font.measure("let");
+        assert_eq!((font.stats.hits, font.stats.misses), (1, 2));
+        // Both were drawn since the last sweep.
+        font.sweep();
+        assert_eq!(font.stats.evictions, 0);
+        font.token("x");
+        font.sweep();
+        assert_eq!(font.stats.evictions, 1);
+        assert!(!font.tokens.contains_key("let"));
+        assert_eq!(font.stats.bytes, held(&font));
+        font.token("let");
+        assert_eq!((font.stats.hits, font.stats.misses), (2, 3));
+        font.clear();
+        assert_eq!(font.stats.evictions, 3);
+        assert_eq!(font.stats.bytes, 0);
+        assert!(font.tokens.is_empty());
+    }
+
+    /// Frame time of a 200 line view scrolling through a 10 000 line buffer, shaping every
+    /// token each frame as inputs did before the cache, then drawing cached blobs.
+    /// `cargo test --release -- --ignored --nocapture token_cache_frame_time`
+    #[test]
+    #[ignore]
+    fn token_cache_frame_time() {
+        let lines: Vec<String> = (0..10_000)
+            .map(|n| format!("let value_{n} = compute({n}, \"text\") + other_{};", n % 97))
+            .collect();
+        let mut surface = skia_safe::surfaces::raster_n32_premul((1600, 3300)).unwrap();
+        let canvas = surface.canvas();
+        let paint = Paint::default();
+        let mut font = asset();
+        let frames = 600;
+        let mut time = |cached: bool| {
+            let start = Instant::now();
+            for frame in 0..frames {
+                let top = frame * 10 % (lines.len() - 200);
+                for (row, line) in lines[top..top + 200].iter().enumerate() {
+                    let y = 16.0 * (row + 1) as f32;
+                    let mut x = 0.0;
+                    for text in line.split_inclusive(' ') {
+                        match cached {
+                            true => {
+                                let token = font.token(text);
+                                for (blob, offset) in &token.runs {
+                                    canvas.draw_text_blob(blob, (x + offset, y), &paint);
+                                }
+                                x += token.width;
+                            }
+                            false => {
+                                for (face, range) in font.runs(text) {
+                                    canvas.draw_str(&text[range.clone()], (x, y), &face, &paint);
+                                    x += face.measure_str(&text[range], None).0;
+                                }
+                            }
+                        }
+                    }
+                }
+                font.sweep();
+            }
+            start.elapsed() / frames as u32
+        };
+        let shaped = time(false);
+        let cached = time(true);
+        println!(
+            "per frame: shaped {:?}, cached {:?}, {:?}",
+            shaped, cached, font.stats
+        );
+        assert!(cached < shaped);
+    }
+}
@@ -0,0 +1,74 @@
+use skia_safe::{FontMgr, Image, Size, surfaces, svg::Dom};
+
+/// How many rasterized sizes an svg keeps around, enough for a resize to not thrash.
+const KEPT: usize = 4;
+
+/// A parsed svg plus the rasters made from it so far.
+pub struct Svg {
+    pub dom: Dom,
+    /// Size in css pixels, from `width`/`height`, else the `viewBox`.
+    pub size: Size,
+    rasters: Vec<((i32, i32), Image)>,
+}
+
+impl Svg {
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        let mut dom = Dom::from_bytes(bytes, FontMgr::new()).ok()?;
+        let root = dom.root();
+        let mut size = root.intrinsic_size();
+        if size.is_empty() {
+            // Same default as browsers when the file says nothing about its size.
+            size = root
+                .view_box()
+                .map(|view| view.size())
+                .unwrap_or(Size::new(300.0, 150.0));
+        }
+        dom.set_container_size(size);
+        Some(Self {
+            dom,
+            size,
+            rasters: Vec::new(),
+        })
+    }
+
+    /// Rasterizes at `width` x `height` device pixels, reusing an earlier result of the same size.
+    pub fn raster(&mut self, width: i32, height: i32) -> Option<&Image> {
+        let key = (width.max(1), height.max(1));
+        match self.rasters.iter().position(|(k, _)| *k == key) {
+            Some(i) => {
+                let hit = self.rasters.remove(i);
+                self.rasters.push(hit);
+            }
+            None => {
+                let mut surface = surfaces::raster_n32_premul(key)?;
+                let canvas = surface.canvas();
+                canvas.scale((
+                    key.0 as f32 / self.size.width,
+                    key.1 as f32 / self.size.height,
+                ));
+                self.dom.render(canvas);
+                self.rasters.push((key, surface.image_snapshot()));
+                if self.rasters.len() > KEPT {
+                    self.rasters.remove(0);
+                }
+            }
+        }
+        self.rasters.last().map(|(_, image)| image)
+    }
+
+    pub fn bytes(&self) -> usize {
+        self.rasters
+            .iter()
+            .map(|(_, image)| image.image_info().compute_min_byte_size())
+            .sum()
+    }
+}
+
+/// Cheap check for svg markup, so sources don't need a `.svg` extension.
+pub fn sniff(bytes: &[u8]) -> bool {
+    let head = &bytes[..bytes.len().min(1024)];
+    let head = String::from_utf8_lossy(head);
+    let head = head.trim_start_matches('\u{feff}').trim_start();
+    (head.starts_with("<?xml") || head.starts_with("<svg") || head.starts_with("<!--"))
+        && head.contains("<svg")
+}
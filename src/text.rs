@@ -0,0 +1,618 @@
+use std::{borrow::Cow, ops::Range};
+
+use juste::style::{ColorId, Font, Size};
+use skia_safe::{
+    Canvas, Color, FontArguments, FontStyle, FourByteTag, Paint, PaintStyle, Path, PathEffect,
+    Point,
+    font_arguments::{VariationPosition, variation_position::Coordinate},
+    font_style::{Weight, Width},
+    textlayout::{
+        Affinity, FontCollection, Paragraph, ParagraphBuilder, ParagraphStyle, StrutStyle,
+        TextAlign, TextDecoration, TextDecorationStyle, TextStyle,
+    },
+};
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Where a text element breaks into lines.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Wrap {
+    /// Only at `\n`.
+    #[default]
+    None,
+    /// Between words.
+    Word,
+    /// Between any two characters, for CJK or long identifiers.
+    /// Characters are grapheme clusters, letters of cursive scripts only break between words.
+    Char,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Align {
+    #[default]
+    Left,
+    Center,
+    Right,
+    /// Stretches the spaces of every line but the last to fill the width.
+    Justify,
+}
+
+pub const ELLIPSIS: &str = "\u{2026}";
+
+/// What a text does when the box `calc_box` leaves it is narrower than the text.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Overflow {
+    /// Drawn past the box.
+    #[default]
+    Visible,
+    Clip,
+    /// Cut at the end with an ellipsis, on the last line `max_lines` keeps too.
+    Ellipsis,
+    /// Cut in the middle with an ellipsis, so both ends of a file path stay readable.
+    Middle,
+    /// Fades out towards the edge of the box.
+    Fade,
+}
+
+/// Options for how a `Genus::Text` is broken into lines, by the pad id of its style,
+/// see `Fonts::set_layout`.
+#[derive(Default, Clone, Copy)]
+pub struct Layout {
+    pub wrap: Wrap,
+    pub align: Align,
+    /// Line height as a multiple of the font size, `None` takes the font's own.
+    pub line_height: Option<f32>,
+    /// Lines past this one are dropped.
+    pub max_lines: Option<usize>,
+    /// Width of the element and what lines wrap to. `None` wraps to the width the parent leaves
+    /// and fits the widest line when nothing wraps, `Size::Child` always fits the widest line.
+    pub width: Option<Size>,
+    pub overflow: Overflow,
+    /// Lets the mouse select the text and the copy shortcut copy it.
+    pub selectable: bool,
+}
+
+impl Layout {
+    /// Whether lines wrap to the width `calc_box` leaves the text rather than one of their own.
+    pub fn wraps_to_parent(&self) -> bool {
+        self.wrap != Wrap::None && self.width.is_none()
+    }
+}
+
+/// Lines drawn along a text.
+#[derive(Clone, Copy)]
+pub struct Line {
+    /// Any mix of `UNDERLINE`, `LINE_THROUGH` and `OVERLINE`.
+    pub kind: TextDecoration,
+    /// `None` takes the text's color.
+    pub color: Option<Color>,
+    /// Multiple of the thickness the font asks for.
+    pub thickness: f32,
+    pub style: TextDecorationStyle,
+}
+
+impl Line {
+    pub fn new(kind: TextDecoration) -> Self {
+        Self {
+            kind,
+            color: None,
+            thickness: 1.0,
+            style: TextDecorationStyle::Solid,
+        }
+    }
+}
+
+/// Drawing options for a whole text, see `Fonts::set_style`, or for one span of it.
+/// Inputs only draw `decoration`, their tokens keep the advances the font measures.
+#[derive(Clone, Default)]
+pub struct Style {
+    pub decoration: Option<Line>,
+    /// Extra space after every character, in pixels.
+    pub letter_spacing: f32,
+    /// Extra space after every space.
+    pub word_spacing: f32,
+    /// OpenType features, `("tnum", 1)` for tabular numbers, `("liga", 0)` turns ligatures off.
+    pub features: Vec<(String, i32)>,
+    /// Variable font axes, `("wght", 650.0)` or `("wdth", 75.0)`.
+    pub axes: Vec<(FourByteTag, f32)>,
+    /// Overrides what the font's `Mode` picks, also selects static faces like semibold.
+    pub weight: Option<Weight>,
+    pub width: Option<Width>,
+}
+
+impl Style {
+    pub fn apply(&self, text_style: &mut TextStyle) {
+        if let Some(line) = &self.decoration {
+            text_style.set_decoration_type(line.kind);
+            text_style.set_decoration_style(line.style);
+            text_style.set_decoration_thickness_multiplier(line.thickness);
+            // Skia paints a transparent decoration in the text's color.
+            text_style.set_decoration_color(line.color.unwrap_or(Color::TRANSPARENT));
+        }
+        text_style
+            .set_letter_spacing(self.letter_spacing)
+            .set_word_spacing(self.word_spacing);
+        for (feature, value) in &self.features {
+            text_style.add_font_feature(feature, *value);
+        }
+        if !self.axes.is_empty() {
+            let coordinates: Vec<_> = self
+                .axes
+                .iter()
+                .map(|(axis, value)| Coordinate {
+                    axis: *axis,
+                    value: *value,
+                })
+                .collect();
+            let arguments = FontArguments::new().set_variation_design_position(VariationPosition {
+                coordinates: &coordinates,
+            });
+            text_style.set_font_arguments(&arguments);
+        }
+        if self.weight.is_some() || self.width.is_some() {
+            let font_style = text_style.font_style();
+            text_style.set_font_style(FontStyle::new(
+                self.weight.unwrap_or(font_style.weight()),
+                self.width.unwrap_or(font_style.width()),
+                font_style.slant(),
+            ));
+        }
+    }
+}
+
+/// Draws `line` under, through or over a run of `width` at baseline `y` drawn with `font`.
+pub fn decorate(
+    canvas: &Canvas,
+    font: &skia_safe::Font,
+    line: &Line,
+    (x, y): (f32, f32),
+    width: f32,
+    paint: &Paint,
+) {
+    let (_, metrics) = font.metrics();
+    let thickness = metrics.underline_thickness().unwrap_or(1.0) * line.thickness;
+    let mut paint = paint.clone();
+    if let Some(color) = line.color {
+        paint.set_color(color);
+    }
+    paint
+        .set_style(PaintStyle::Stroke)
+        .set_stroke_width(thickness);
+    let dash = match line.style {
+        TextDecorationStyle::Dotted => PathEffect::dash(&[thickness, thickness], 0.0),
+        TextDecorationStyle::Dashed => PathEffect::dash(&[thickness * 4.0, thickness * 2.0], 0.0),
+        _ => None,
+    };
+    paint.set_path_effect(dash);
+    let mut offsets = Vec::new();
+    if line.kind.contains(TextDecoration::UNDERLINE) {
+        offsets.push(metrics.underline_position().unwrap_or(thickness));
+    }
+    if line.kind.contains(TextDecoration::LINE_THROUGH) {
+        offsets.push(
+            metrics
+                .strikeout_position()
+                .unwrap_or(metrics.x_height / -2.0),
+        );
+    }
+    if line.kind.contains(TextDecoration::OVERLINE) {
+        offsets.push(metrics.ascent);
+    }
+    for offset in offsets {
+        let y = y + offset;
+        match line.style {
+            TextDecorationStyle::Wavy => {
+                let mut path = Path::new();
+                path.move_to((x, y));
+                let (step, mut at, mut up) = (thickness * 2.0, x, true);
+                while at < x + width {
+                    let bend = match up {
+                        true => -thickness * 2.0,
+                        false => thickness * 2.0,
+                    };
+                    path.quad_to((at + step / 2.0, y + bend), (at + step, y));
+                    at += step;
+                    up = !up;
+                }
+                canvas.draw_path(&path, &paint);
+            }
+            TextDecorationStyle::Double => {
+                canvas.draw_line((x, y), (x + width, y), &paint);
+                let y = y + thickness * 2.0;
+                canvas.draw_line((x, y), (x + width, y), &paint);
+            }
+            _ => {
+                canvas.draw_line((x, y), (x + width, y), &paint);
+            }
+        }
+    }
+}
+
+/// One run of a `Rich` text, `None`s take the `Text` element's own.
+#[derive(Clone)]
+pub struct Span {
+    pub text: String,
+    pub font: Option<Font>,
+    pub color: Option<Color>,
+    /// Overrides the font's size.
+    pub size: Option<f32>,
+    pub style: Option<Style>,
+    pub background: Option<Color>,
+}
+
+impl Span {
+    pub fn new(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            font: None,
+            color: None,
+            size: None,
+            style: None,
+            background: None,
+        }
+    }
+
+    fn apply(&self, inherited: &Style, text_style: &mut TextStyle) {
+        self.style.as_ref().unwrap_or(inherited).apply(text_style);
+        if let Some(background) = self.background {
+            let mut paint = Paint::default();
+            paint.set_color(background);
+            text_style.set_background_paint(&paint);
+        }
+    }
+}
+
+/// Spans laid out as one paragraph, shown instead of its own text by every `Genus::Text`
+/// whose style has the pad id they were registered under, see `Fonts::set_rich`.
+#[derive(Clone, Default)]
+pub struct Rich {
+    pub spans: Vec<Span>,
+}
+
+/// A selection in a selectable `Genus::Text`, in utf-16 units of what was shaped.
+#[derive(Clone)]
+pub struct Selection {
+    pub key: Key,
+    /// Where the text was painted, tells equal texts apart.
+    pub origin: Point,
+    pub anchor: usize,
+    pub focus: usize,
+}
+
+impl Selection {
+    pub fn range(&self) -> Range<usize> {
+        self.anchor.min(self.focus)..self.anchor.max(self.focus)
+    }
+}
+
+/// What a shaped paragraph is cached by.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct Key {
+    pub font: Font,
+    /// Pad id of the text's style, which its `Layout` hangs off.
+    pub pad: ColorId,
+    pub text: String,
+    pub color: u32,
+}
+
+/// A shaped paragraph, laid out for `width`.
+pub struct Shaped {
+    pub paragraph: Paragraph,
+    pub width: f32,
+    pub used: u64,
+}
+
+/// Style of a run, glyphs are taken from the first of `families` that has them, then from the system.
+pub fn text_style(
+    families: &[String],
+    size: f32,
+    style: FontStyle,
+    color: Color,
+    layout: &Layout,
+) -> TextStyle {
+    let mut text_style = TextStyle::new();
+    text_style
+        .set_font_families(families)
+        .set_font_size(size)
+        .set_font_style(style)
+        .set_color(color);
+    if let Some(height) = layout.line_height {
+        text_style.set_height(height).set_height_override(true);
+    }
+    text_style
+}
+
+/// Shapes `runs` as one paragraph, the first run's style sets the paragraph's defaults.
+pub fn shape(
+    collection: &FontCollection,
+    layout: &Layout,
+    runs: &[(TextStyle, String)],
+) -> Paragraph {
+    let mut paragraph_style = ParagraphStyle::new();
+    if let Some((text_style, _)) = runs.first() {
+        paragraph_style
+            .set_text_style(text_style)
+            .set_strut_style(strut(text_style, runs.len() == 1));
+    }
+    paragraph_style
+        .set_max_lines(layout.max_lines)
+        .set_text_align(match layout.align {
+            Align::Left => TextAlign::Left,
+            Align::Center => TextAlign::Center,
+            Align::Right => TextAlign::Right,
+            Align::Justify => TextAlign::Justify,
+        });
+    // Without a line limit skia ellipsizes the first line and drops the rest.
+    if layout.overflow == Overflow::Ellipsis && layout.max_lines.is_some() {
+        paragraph_style.set_ellipsis(ELLIPSIS);
+    }
+    let mut builder = ParagraphBuilder::new(&paragraph_style, collection.clone());
+    for (text_style, text) in runs {
+        builder.push_style(text_style);
+        builder.add_text(shown(text, layout));
+        builder.pop();
+    }
+    builder.build()
+}
+
+/// Lines at least as tall as the font's ascent, descent and leading, whatever glyphs they hold.
+/// `force` also keeps fallback glyphs, e.g. emoji, from making a line taller.
+fn strut(text_style: &TextStyle, force: bool) -> StrutStyle {
+    let families = text_style.font_families();
+    let families: Vec<&str> = families.iter().collect();
+    let mut strut = StrutStyle::new();
+    strut
+        .set_font_families(&families)
+        .set_font_size(text_style.font_size())
+        .set_font_style(text_style.font_style())
+        .set_height(text_style.height())
+        .set_height_override(text_style.height_override())
+        .set_strut_enabled(true)
+        .set_force_strut_height(force);
+    strut
+}
+
+/// Run styles for the spans of `rich`, `base` is the style a span starts from
+/// and `inherited` what spans without a `Style` of their own get.
+pub fn span_styles(
+    rich: &Rich,
+    inherited: &Style,
+    mut base: impl FnMut(&Span) -> Option<TextStyle>,
+) -> Option<Vec<(TextStyle, String)>> {
+    rich.spans
+        .iter()
+        .map(|span| {
+            let mut style = base(span)?;
+            span.apply(inherited, &mut style);
+            Some((style, span.text.clone()))
+        })
+        .collect()
+}
+
+/// Span and character index within it under `position`, from `Paragraph::get_glyph_position_at_coordinate`.
+pub fn locate(
+    rich: &Rich,
+    position: i32,
+    affinity: Affinity,
+    layout: &Layout,
+) -> Option<(usize, usize)> {
+    // Upstream means the point is on the trailing half of the character before.
+    let mut utf16 = match affinity {
+        Affinity::Upstream => position - 1,
+        _ => position,
+    }
+    .max(0) as usize;
+    for (i, span) in rich.spans.iter().enumerate() {
+        let shown = shown(&span.text, layout);
+        let len = shown.encode_utf16().count();
+        if utf16 < len {
+            let before = &shown[..byte_index(&shown, utf16)];
+            return Some((i, before.chars().filter(|c| !inserted(*c, layout)).count()));
+        }
+        utf16 -= len;
+    }
+    None
+}
+
+/// Utf-16 range of the line at height `y`.
+pub fn line_at(paragraph: &Paragraph, y: f32) -> Range<usize> {
+    let lines = paragraph.get_line_metrics();
+    let line = lines
+        .iter()
+        .find(|line| (y as f64) < line.baseline + line.descent)
+        .or(lines.last());
+    match line {
+        Some(line) => {
+            let mid = (line.baseline - (line.ascent - line.descent) / 2.0) as f32;
+            let left = (line.left - 1.0) as f32;
+            let right = (line.left + line.width + 1.0) as f32;
+            let start = paragraph.get_glyph_position_at_coordinate((left, mid));
+            let end = paragraph.get_glyph_position_at_coordinate((right, mid));
+            start.position.max(0) as usize..end.position.max(0) as usize
+        }
+        None => 0..0,
+    }
+}
+
+/// Zero width spaces `Wrap::Char` put in, which are not part of the text.
+pub fn inserted(c: char, layout: &Layout) -> bool {
+    layout.wrap == Wrap::Char && c == '\u{200b}'
+}
+
+/// The text a paragraph is actually built from, which its positions index into.
+pub fn shown<'a>(text: &'a str, layout: &Layout) -> Cow<'a, str> {
+    match layout.wrap {
+        Wrap::Char => Cow::Owned(breakable(text)),
+        _ => Cow::Borrowed(text),
+    }
+}
+
+/// What is left of the single line `text` once cut down to `width`, an ellipsis `ellipsis` wide included.
+/// `paragraph` was built from `shown(text)`, the cut is made in `text` so shaping it again
+/// doesn't break it up twice.
+pub fn truncate(
+    paragraph: &Paragraph,
+    text: &str,
+    layout: &Layout,
+    width: f32,
+    ellipsis: f32,
+    middle: bool,
+) -> String {
+    let room = (width - ellipsis).max(0.0);
+    let y = paragraph.height() / 2.0;
+    let shown = shown(text, layout);
+    let at = |x: f32| {
+        let position = paragraph.get_glyph_position_at_coordinate((x, y)).position;
+        source_index(
+            text,
+            &shown,
+            byte_index(&shown, position.max(0) as usize),
+            layout,
+        )
+    };
+    match middle {
+        false => format!("{}{ELLIPSIS}", text[..at(room)].trim_end()),
+        true => {
+            let head = at(room / 2.0);
+            let tail = at(paragraph.max_intrinsic_width() - room / 2.0).max(head);
+            format!("{}{ELLIPSIS}{}", &text[..head], &text[tail..])
+        }
+    }
+}
+
+/// Byte offset of the `utf16`th code unit, paragraph positions count in those.
+pub fn byte_index(text: &str, utf16: usize) -> usize {
+    let mut units = 0;
+    for (i, c) in text.char_indices() {
+        if units >= utf16 {
+            return i;
+        }
+        units += c.len_utf16();
+    }
+    text.len()
+}
+
+/// Byte offset in `text` of the byte offset `index` in `shown`, what `shown` inserted left out.
+fn source_index(text: &str, shown: &str, index: usize, layout: &Layout) -> usize {
+    let chars = shown[..index]
+        .chars()
+        .filter(|c| !inserted(*c, layout))
+        .count();
+    text.char_indices()
+        .nth(chars)
+        .map_or(text.len(), |(i, _)| i)
+}
+
+/// Lays out for `width`, lines that don't wrap are then laid out again
+/// at the widest one so they still align against each other.
+pub fn lay_out(paragraph: &mut Paragraph, layout: &Layout, width: f32) {
+    let wrap = match layout.wrap {
+        Wrap::None => f32::INFINITY,
+        _ => width,
+    };
+    paragraph.layout(wrap);
+    if layout.align != Align::Left && !wrap.is_finite() {
+        let widest = paragraph.max_intrinsic_width().ceil();
+        paragraph.layout(match width.is_finite() {
+            true => width.max(widest),
+            false => widest,
+        });
+    }
+}
+
+/// Puts a zero width space between extended grapheme clusters so a line may break anywhere,
+/// marks, conjuncts, flags, keycaps and emoji sequences stay whole.
+fn breakable(text: &str) -> String {
+    let mut out = String::with_capacity(text.len() * 2);
+    // Nothing goes before the first cluster, as nothing goes around a line break.
+    let mut last = '\n';
+    for cluster in text.graphemes(true) {
+        let first = cluster.chars().next().unwrap_or('\n');
+        let joined = last == '\u{200d}' || (cursive(last) && cursive(first));
+        if last != '\n' && first != '\n' && !joined {
+            out.push('\u{200b}');
+        }
+        out.push_str(cluster);
+        last = cluster.chars().last().unwrap_or(last);
+    }
+    out
+}
+
+/// Letters of scripts that join their neighbours, a break between two would undo the joining.
+fn cursive(c: char) -> bool {
+    matches!(
+        c,
+        // Arabic, Syriac, Thaana, NKo, Mandaic and the Arabic extensions.
+        '\u{0600}'..='\u{08ff}'
+            | '\u{1800}'..='\u{18af}'
+            | '\u{a840}'..='\u{a87f}'
+            | '\u{fb50}'..='\u{fdff}'
+            | '\u{fe70}'..='\u{fefe}'
+            | '\u{10ac0}'..='\u{10aff}'
+            | '\u{10d00}'..='\u{10d3f}'
+            | '\u{10f30}'..='\u{10f6f}'
+            | '\u{1e900}'..='\u{1e95f}'
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pieces(text: &str) -> Vec<String> {
+        breakable(text)
+            .split('\u{200b}')
+            .map(|s| s.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn breaks_between_clusters() {
+        assert_eq!(pieces("ab\ncd"), ["a", "b\nc", "d"]);
+        // e with a combining acute, a flag, a keycap and a family emoji.
+        assert_eq!(
+            pieces("e\u{301}\u{1f1eb}\u{1f1f7}1\u{fe0f}\u{20e3}\u{1f468}\u{200d}\u{1f469}"),
+            [
+                "e\u{301}",
+                "\u{1f1eb}\u{1f1f7}",
+                "1\u{fe0f}\u{20e3}",
+                "\u{1f468}\u{200d}\u{1f469}"
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_indic_clusters_whole() {
+        // Devanagari ksha with a virama, then ki with a vowel sign.
+        assert_eq!(
+            pieces("\u{915}\u{94d}\u{937}\u{915}\u{93f}"),
+            ["\u{915}\u{94d}\u{937}", "\u{915}\u{93f}"]
+        );
+    }
+
+    #[test]
+    fn keeps_arabic_joined() {
+        // Salam with harakat, a space, then latin.
+        let text = "\u{633}\u{64e}\u{644}\u{627}\u{645} ab";
+        assert_eq!(
+            pieces(text),
+            ["\u{633}\u{64e}\u{644}\u{627}\u{645}", " ", "a", "b"]
+        );
+    }
+
+    #[test]
+    fn maps_shown_offsets_back_to_the_source() {
+        let layout = Layout {
+            wrap: Wrap::Char,
+            ..Layout::default()
+        };
+        let text = "ab\u{e9}c";
+        let shown = shown(text, &layout);
+        let at = |c: char| shown.find(c).unwrap();
+        assert_eq!(source_index(text, &shown, 0, &layout), 0);
+        assert_eq!(source_index(text, &shown, at('b'), &layout), 1);
+        assert_eq!(source_index(text, &shown, at('c'), &layout), 4);
+        assert_eq!(source_index(text, &shown, shown.len(), &layout), text.len());
+        let plain = Layout::default();
+        assert_eq!(source_index(text, text, 2, &plain), 2);
+    }
+}
@@ -0,0 +1,139 @@
+use std::path::PathBuf;
+
+/// Payload of a `data:` uri, base64 or percent-encoded.
+pub fn data(uri: &str) -> Option<Vec<u8>> {
+    let rest = uri.strip_prefix("data:")?;
+    let (header, payload) = rest.split_once(',')?;
+    match header.ends_with(";base64") {
+        true => base64(payload),
+        false => Some(percent_decode(payload)),
+    }
+}
+
+/// Local path of a `file://` uri, `file:///C:/x.png` becomes `C:/x.png` on windows.
+pub fn file_path(uri: &str) -> Option<PathBuf> {
+    let rest = uri.strip_prefix("file://")?;
+    // Only the empty host and localhost point at this machine.
+    let rest = rest.strip_prefix("localhost").unwrap_or(rest);
+    if !rest.starts_with('/') {
+        return None;
+    }
+    let path = String::from_utf8(percent_decode(rest)).ok()?;
+    let path = match path.as_bytes() {
+        [b'/', drive, b':', ..] if cfg!(windows) && drive.is_ascii_alphabetic() => &path[1..],
+        _ => &path,
+    };
+    Some(PathBuf::from(path))
+}
+
+fn percent_decode(text: &str) -> Vec<u8> {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        // from_str_radix would also take a sign, `%+5` is left as it is.
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .filter(|h| h.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                out.push(byte);
+                i += 1;
+            }
+        }
+    }
+    out
+}
+
+/// Standard and url-safe alphabets, padding and whitespace are skipped.
+/// A lone character at the end can't make up a byte, so the text is malformed.
+fn base64(text: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(text.len() * 3 / 4);
+    let mut acc = 0u32;
+    let mut bits = 0;
+    for byte in text.bytes() {
+        let value = match byte {
+            b'A'..=b'Z' => byte - b'A',
+            b'a'..=b'z' => byte - b'a' + 26,
+            b'0'..=b'9' => byte - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            b'=' | b' ' | b'\n' | b'\r' | b'\t' => continue,
+            _ => return None,
+        };
+        acc = (acc << 6) | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+        }
+    }
+    match bits {
+        6 => None,
+        _ => Some(out),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base64_alphabets_and_padding() {
+        assert_eq!(base64("aGk/Pz4+").unwrap(), b"hi??>>");
+        assert_eq!(base64("aGk_Pz4-").unwrap(), b"hi??>>");
+        assert_eq!(base64("aGk=").unwrap(), b"hi");
+        assert_eq!(base64("aGk").unwrap(), b"hi");
+        assert_eq!(base64("aA==").unwrap(), b"h");
+        assert_eq!(base64("aGVs\nbG8=").unwrap(), b"hello");
+        assert_eq!(base64("").unwrap(), b"");
+    }
+
+    #[test]
+    fn base64_rejects_malformed() {
+        assert!(base64("aGk*").is_none());
+        assert!(base64("aGkhZ").is_none());
+        assert!(base64("a").is_none());
+    }
+
+    #[test]
+    fn percent_needs_two_hex_digits() {
+        assert_eq!(percent_decode("a%20b%2Fc"), b"a b/c");
+        assert_eq!(percent_decode("%+5%-1"), b"%+5%-1");
+        assert_eq!(percent_decode("%g0%2"), b"%g0%2");
+        assert_eq!(percent_decode("%"), b"%");
+    }
+
+    #[test]
+    fn data_uris() {
+        assert_eq!(data("data:image/png;base64,aGk=").unwrap(), b"hi");
+        assert_eq!(data("data:,a%20b").unwrap(), b"a b");
+        assert!(data("data:;base64,a").is_none());
+        assert!(data("data:nocomma").is_none());
+    }
+
+    #[test]
+    fn file_uris() {
+        assert_eq!(
+            file_path("file:///tmp/a%20b.png").unwrap(),
+            PathBuf::from("/tmp/a b.png")
+        );
+        assert_eq!(
+            file_path("file://localhost/tmp/a.png").unwrap(),
+            PathBuf::from("/tmp/a.png")
+        );
+        assert!(file_path("file://example.com/tmp/a.png").is_none());
+        assert!(file_path("http://localhost/a.png").is_none());
+        let drive = file_path("file:///C:/img/a.png").unwrap();
+        match cfg!(windows) {
+            true => assert_eq!(drive, PathBuf::from("C:/img/a.png")),
+            false => assert_eq!(drive, PathBuf::from("/C:/img/a.png")),
+        }
+    }
+}